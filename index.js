@@ -1,13 +1,16 @@
 const rust = import ('./pkg');
 const canvas = document.getElementById('rustCanvas');
-const gl = canvas.getContext('webgl', { antialias: true });
 
 rust.then(m => {
-    if (!gl) {
-        alert('Failed to initialize WebGL');
+    let rustClient;
+    try {
+        rustClient = new m.Client(canvas, { antialias: true, backgroundColor: [0, 0, 0, 1] });
+    } catch (e) {
+        alert('Failed to initialize WebGL: ' + e);
         return;
     }
-    const rustClient = new m.Client();
+    // Same context the client created, used here to keep the viewport in sync with the canvas size
+    const gl = canvas.getContext('webgl');
     const initialTime = Date.now();
 
     const FPS_THROTTLE = 1000.0 / 30.0; // milliseconds / frames
@@ -35,7 +38,7 @@ rust.then(m => {
             rustClient.update(elapsedTime, window.innerHeight, window.innerWidth);
             rustClient.render();
         }
-        
+
     }
     requestAnimationFrame(render);
     const width = canvas.clientWidth;
@@ -1,34 +1,49 @@
-use lazy_static::lazy_static;
-use std::sync::Arc;
-use std::sync::Mutex;
+use std::cell::RefCell;
+use std::rc::Rc;
+use nalgebra::{UnitQuaternion, Vector3};
+use crate::common_funcs as cf;
+use crate::constants as c;
 
-lazy_static! {
-    static ref APP_STATE: Mutex<Arc<AppState>> = Mutex::new(Arc::new(AppState::new()));
+// Each Client owns its state and shares it with the event handlers attached to its canvas
+pub type SharedAppState = Rc<RefCell<AppState>>;
+
+// An active touch or pen contact, y is flipped to WebGL coordinates like the mouse position
+#[derive(Clone, Copy)]
+pub struct TouchPoint {
+    pub id: i32,
+    pub x: f32,
+    pub y: f32,
 }
 
-pub fn update_dynamic_data(time: f32, canvas_height: f32, canvas_width: f32) {
-    let min_height_width = canvas_height.min(canvas_width);
-    let display_size = 0.9 * min_height_width;
-    let half_display_size = display_size / 2.;
-    let half_canvas_height = canvas_height / 2.;
-    let half_canvas_width = canvas_width / 2.;  
-    
-    // Center the display area in canvas
-    let mut data = APP_STATE.lock().unwrap();
-    *data = Arc::new (AppState {
-        time,
-        canvas_height,
-        canvas_width,
-        control_bottom: half_canvas_height - half_display_size,
-        control_top: half_canvas_height + half_display_size,
-        control_left: half_canvas_width - half_display_size,
-        control_right: half_canvas_width + half_display_size,
-        ..*data.clone()
-    });
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViewPreset {
+    // Looking down onto the x-z plane
+    Top,
+    // Looking along -z, the surface seen edge-on
+    Front,
+    // Looking along -x
+    Side,
+    Isometric,
+    // Back to the initial orientation, zoom and pan
+    Reset,
 }
 
-pub fn get_curr_state() -> Arc<AppState> {
-    APP_STATE.lock().unwrap().clone()
+impl std::str::FromStr for ViewPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "top" => Ok(ViewPreset::Top),
+            "front" => Ok(ViewPreset::Front),
+            "side" => Ok(ViewPreset::Side),
+            "isometric" => Ok(ViewPreset::Isometric),
+            "reset" => Ok(ViewPreset::Reset),
+            _ => Err(format!(
+                "Unknown view '{}', expected one of top, front, side, isometric, reset",
+                s
+            )),
+        }
+    }
 }
 
 pub struct AppState {
@@ -39,15 +54,40 @@ pub struct AppState {
     pub control_left: f32,
     pub control_right: f32,
     pub mouse_down: bool,
+    // Dragging moves the graph instead of rotating it (right button or shift held)
+    pub mouse_pan: bool,
     pub mouse_x: f32,
     pub mouse_y: f32,
-    pub rotation_angle_x_axis: f32,
-    pub rotation_angle_y_axis: f32,
+    // One finger rotates, two fingers pinch-zoom and pan
+    pub touches: Vec<TouchPoint>,
+    // Orientation of the graph, driven by an arcball
+    pub rotation: UnitQuaternion<f32>,
+    // Momentum after a drag is released, as a rotation axis scaled by radians per millisecond
+    pub angular_velocity: Vector3<f32>,
+    // Drag rotation accumulated since the last frame, used to measure angular_velocity
+    pub pending_drag_rotation: UnitQuaternion<f32>,
+    // Exponential decay rate of the momentum per second, 0 keeps spinning forever
+    pub rotation_damping: f32,
+    // Time of the last frame in which a drag turned the graph
+    pub last_drag_time: f32,
+    pub zoom: f32,
+    // In units of half the canvas height
+    pub pan_x: f32,
+    pub pan_y: f32,
+    pub orthographic: bool,
+    // 0 is perspective, 1 is orthographic, in between while switching
+    pub orthographic_blend: f32,
     pub time: f32,
 }
 
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AppState {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             canvas_height: 0.,
             canvas_width: 0.,
@@ -56,52 +96,243 @@ impl AppState {
             control_left: 0.,
             control_right: 0.,
             mouse_down: false,
+            mouse_pan: false,
             mouse_x: 0.,
             mouse_y: 0.,
-            rotation_angle_x_axis: -0.5,
-            rotation_angle_y_axis: 0.5,
+            touches: Vec::new(),
+            rotation: cf::rotation_from_euler_angles(-0.5, 0.5),
+            angular_velocity: Vector3::zeros(),
+            pending_drag_rotation: UnitQuaternion::identity(),
+            rotation_damping: c::DEFAULT_ROTATION_DAMPING,
+            last_drag_time: 0.,
+            zoom: 1.,
+            pan_x: 0.,
+            pan_y: 0.,
+            orthographic: false,
+            orthographic_blend: 0.,
             time: 0.,
         }
     }
-}
 
-pub fn update_mouse_down(x:f32, y: f32, mouse_down: bool) {
-    let mut data = APP_STATE.lock().unwrap();
-    *data = Arc::new (AppState {
-        mouse_down,
-        mouse_x: x,
+    pub fn update_dynamic_data(&mut self, time: f32, canvas_height: f32, canvas_width: f32) {
+        let min_height_width = canvas_height.min(canvas_width);
+        let display_size = 0.9 * min_height_width;
+        let half_display_size = display_size / 2.;
+        let half_canvas_height = canvas_height / 2.;
+        let half_canvas_width = canvas_width / 2.;
+
+        self.update_inertia(time - self.time);
+        self.update_projection_transition(time - self.time);
+        self.time = time;
+        self.canvas_height = canvas_height;
+        self.canvas_width = canvas_width;
+        // Center the display area in canvas
+        self.control_bottom = half_canvas_height - half_display_size;
+        self.control_top = half_canvas_height + half_display_size;
+        self.control_left = half_canvas_width - half_display_size;
+        self.control_right = half_canvas_width + half_display_size;
+    }
+
+    fn is_rotating_by_drag(&self) -> bool {
+        (self.mouse_down && !self.mouse_pan) || self.touches.len() == 1
+    }
+
+    // While dragging, measures how fast the graph is being turned. Once released,
+    // keeps turning it at that speed, slowing down according to rotation_damping
+    fn update_inertia(&mut self, elapsed_time: f32) {
+        if elapsed_time <= 0. {
+            return;
+        }
+        if self.is_rotating_by_drag() && self.time + elapsed_time - self.last_drag_time > c::INERTIA_HOLD_MS {
+            // Holding still before letting go drops the momentum
+            self.angular_velocity = Vector3::zeros();
+        } else if self.is_rotating_by_drag() {
+            let measured_velocity = self.pending_drag_rotation.scaled_axis() / elapsed_time;
+            // Smooth over the last few frames so a single jittery event doesn't dominate
+            self.angular_velocity = self.angular_velocity.lerp(&measured_velocity, 0.5);
+        } else if self.angular_velocity.norm() > c::MIN_ANGULAR_VELOCITY {
+            let step = UnitQuaternion::from_scaled_axis(self.angular_velocity * elapsed_time);
+            self.rotation = UnitQuaternion::new_normalize((step * self.rotation).into_inner());
+            self.angular_velocity *= (-self.rotation_damping * elapsed_time / 1000.).exp();
+        } else {
+            self.angular_velocity = Vector3::zeros();
+        }
+        self.pending_drag_rotation = UnitQuaternion::identity();
+    }
+
+    fn update_projection_transition(&mut self, elapsed_time: f32) {
+        let target = if self.orthographic { 1. } else { 0. };
+        let step = elapsed_time.max(0.) / c::PROJECTION_TRANSITION_MS;
+        self.orthographic_blend = if target > self.orthographic_blend {
+            (self.orthographic_blend + step).min(target)
+        } else {
+            (self.orthographic_blend - step).max(target)
+        };
+    }
+
+    fn stop_inertia(&mut self) {
+        self.angular_velocity = Vector3::zeros();
+        self.pending_drag_rotation = UnitQuaternion::identity();
+    }
+
+    pub fn update_mouse_down(&mut self, x: f32, y: f32, mouse_down: bool, pan: bool) {
+        if mouse_down {
+            // Grabbing the graph stops it from spinning
+            self.stop_inertia();
+        }
+        self.mouse_down = mouse_down;
+        self.mouse_pan = mouse_down && pan;
+        self.mouse_x = x;
         // Flip y-axis to match WebGL coordinates
-        mouse_y: data.canvas_height - y,
-        ..*data.clone()
-    });
-}
+        self.mouse_y = self.canvas_height - y;
+    }
+
+    pub fn update_mouse_position(&mut self, x: f32, y: f32) {
+        // Flip y-axis to match WebGL coordinates
+        let mouse_y =  self.canvas_height - y;
+        let delta_x = x - self.mouse_x;
+        let delta_y = mouse_y - self.mouse_y;
+
+        if self.mouse_down && self.mouse_pan {
+            self.mouse_x = x;
+            self.mouse_y = mouse_y;
+            self.pan_by(2. * delta_x / self.canvas_height, 2. * delta_y / self.canvas_height);
+            return;
+        }
+
+        if self.mouse_down {
+            self.rotate_by_drag(self.mouse_x, self.mouse_y, x, mouse_y);
+        }
+        self.mouse_x = x;
+        self.mouse_y = mouse_y;
+    }
+
+    // Arcball rotation: both points are projected onto a virtual sphere filling the
+    // control area and the graph turns by the rotation that carries one onto the other
+    fn rotate_by_drag(&mut self, from_x: f32, from_y: f32, to_x: f32, to_y: f32) {
+        // The sphere follows the graph when it is panned and zoomed
+        let center_x = (self.control_left + self.control_right) / 2. + self.pan_x * self.canvas_height / 2.;
+        let center_y = (self.control_bottom + self.control_top) / 2. + self.pan_y * self.canvas_height / 2.;
+        let radius = self.zoom * (self.control_top - self.control_bottom) / 2.;
+        // Events without motion would only add rounding error to the rotation
+        if radius <= 0. || (from_x == to_x && from_y == to_y) {
+            return;
+        }
+        let from = cf::arcball_vector(from_x, from_y, center_x, center_y, radius);
+        let to = cf::arcball_vector(to_x, to_y, center_x, center_y, radius);
+        if let Some(drag_rotation) = UnitQuaternion::rotation_between(&from, &to) {
+            // The sphere lives in view space, so the drag is applied after the current rotation
+            self.rotation = UnitQuaternion::new_normalize((drag_rotation * self.rotation).into_inner());
+            self.pending_drag_rotation = drag_rotation * self.pending_drag_rotation;
+            self.last_drag_time = self.time;
+        }
+    }
+
+    pub fn update_touch_down(&mut self, id: i32, x: f32, y: f32) {
+        self.stop_inertia();
+        self.touches.retain(|touch| touch.id != id);
+        self.touches.push(TouchPoint {
+            id,
+            x,
+            // Flip y-axis to match WebGL coordinates
+            y: self.canvas_height - y,
+        });
+    }
 
-pub fn update_mouse_position(x: f32, y: f32) {
-    let mut data = APP_STATE.lock().unwrap();
-
-    // Flip y-axis to match WebGL coordinates
-    let mouse_y =  data.canvas_height - y;
-    let delta_x = x - data.mouse_x;
-    let delta_y = mouse_y - data.mouse_y;
-
-    // Motion in x-axis is rotation around y-axis
-    let rotation_x_delta = if data.mouse_down {
-        std::f32::consts::PI * delta_y / data.canvas_width 
-    } else {
-        0.
-    };
-
-    // Motion in y-axis is rotation around x-axis
-    let rotation_y_delta = if data.mouse_down {
-        - std::f32::consts::PI * delta_x / data.canvas_width 
-    } else {
-        0.
-    };
-    *data = Arc::new (AppState {
-        mouse_x: x,
-        mouse_y,
-        rotation_angle_x_axis: data.rotation_angle_x_axis + rotation_x_delta,
-        rotation_angle_y_axis: data.rotation_angle_y_axis + rotation_y_delta,
-        ..*data.clone()
-    });
+    pub fn update_touch_up(&mut self, id: i32) {
+        self.touches.retain(|touch| touch.id != id);
+    }
+
+    pub fn update_touch_move(&mut self, id: i32, x: f32, y: f32) {
+        let index = match self.touches.iter().position(|touch| touch.id == id) {
+            Some(index) => index,
+            None => return,
+        };
+        let previous = self.touches[index];
+        let current = TouchPoint {
+            id,
+            x,
+            // Flip y-axis to match WebGL coordinates
+            y: self.canvas_height - y,
+        };
+        self.touches[index] = current;
+
+        match self.touches.len() {
+            1 => self.rotate_by_drag(previous.x, previous.y, current.x, current.y),
+            // Only the first two contacts take part in a pinch
+            n if n >= 2 && index < 2 => {
+                let other = self.touches[1 - index];
+                let distance = |a: TouchPoint, b: TouchPoint| ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt();
+                let previous_distance = distance(previous, other);
+                if previous_distance > 1. {
+                    let ratio = distance(current, other) / previous_distance;
+                    self.zoom = (self.zoom * ratio).clamp(c::MIN_ZOOM, c::MAX_ZOOM);
+                }
+                // The midpoint moves by half of this contact's motion
+                let center_delta_x = (current.x - previous.x) / 2.;
+                let center_delta_y = (current.y - previous.y) / 2.;
+                self.pan_by(2. * center_delta_x / self.canvas_height, 2. * center_delta_y / self.canvas_height);
+            }
+            _ => {}
+        }
+    }
+
+    // `delta_y` is the wheel delta in pixels, scrolling up (negative) zooms in
+    pub fn update_mouse_wheel(&mut self, delta_y: f32) {
+        self.zoom = (self.zoom * (-delta_y * c::ZOOM_SPEED).exp()).clamp(c::MIN_ZOOM, c::MAX_ZOOM);
+    }
+
+    pub fn pan_by(&mut self, delta_x: f32, delta_y: f32) {
+        self.pan_x = (self.pan_x + delta_x).clamp(-c::MAX_PAN, c::MAX_PAN);
+        self.pan_y = (self.pan_y + delta_y).clamp(-c::MAX_PAN, c::MAX_PAN);
+    }
+
+    pub fn set_view_preset(&mut self, preset: ViewPreset) {
+        use std::f32::consts::FRAC_PI_2;
+        let initial = AppState::new();
+        self.stop_inertia();
+        self.rotation = match preset {
+            ViewPreset::Top => UnitQuaternion::from_axis_angle(&Vector3::x_axis(), FRAC_PI_2),
+            ViewPreset::Front => UnitQuaternion::identity(),
+            ViewPreset::Side => UnitQuaternion::from_axis_angle(&Vector3::y_axis(), -FRAC_PI_2),
+            // Turn the (1, 1, 1) diagonal towards the viewer
+            ViewPreset::Isometric => {
+                UnitQuaternion::from_axis_angle(&Vector3::x_axis(), (1. / 3f32.sqrt()).asin())
+                    * UnitQuaternion::from_axis_angle(&Vector3::y_axis(), -std::f32::consts::FRAC_PI_4)
+            }
+            ViewPreset::Reset => {
+                self.zoom = initial.zoom;
+                self.pan_x = initial.pan_x;
+                self.pan_y = initial.pan_y;
+                initial.rotation
+            }
+        };
+    }
+
+    // Chooses zoom and pan so the surface, as seen with the current rotation, fills the
+    // control area. `positions` holds x, y, z per vertex and `y_vals` the current heights
+    pub fn fit_to_data(&mut self, positions: &[f32], y_vals: &[f32]) {
+        let mut min = Vector3::repeat(f32::INFINITY);
+        let mut max = Vector3::repeat(f32::NEG_INFINITY);
+        for (position, y) in positions.chunks_exact(3).zip(y_vals.iter()) {
+            let point = self.rotation * Vector3::new(position[0], *y, position[2]);
+            min = min.inf(&point);
+            max = max.sup(&point);
+        }
+        if !min.x.is_finite() || !max.x.is_finite() {
+            return;
+        }
+
+        // At zoom 1 the square [-1, 1] x [-1, 1] fills the control area (minus the margins)
+        let half_extent = ((max.x - min.x).max(max.y - min.y) / 2.).max(1e-3);
+        // Leave some room for the parts nearer to the camera, which perspective makes bigger
+        self.zoom = (c::FIT_TO_DATA_FILL / half_extent).clamp(c::MIN_ZOOM, c::MAX_ZOOM);
+
+        // Pan is in units of half the canvas height, like in get_3d_matrices
+        let graph_height = self.control_top - self.control_bottom - 2. * c::GRAPH_MARGIN;
+        let scale = self.zoom * graph_height / self.canvas_height;
+        self.pan_x = 0.;
+        self.pan_y = 0.;
+        self.pan_by(-scale * (min.x + max.x) / 2., -scale * (min.y + max.y) / 2.);
+    }
 }
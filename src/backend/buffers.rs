@@ -0,0 +1,164 @@
+// Typed wrappers around RenderBackend buffer objects, so the programs do not have to
+// pair bind_buffer / buffer_data / vertex_attrib_pointer calls and track sizes by hand
+use std::marker::PhantomData;
+use web_sys::WebGlRenderingContext as GL;
+use crate::common_funcs::MeshIndices;
+use crate::error::Error;
+use super::RenderBackend;
+
+// Element types a buffer can hold
+pub trait BufferData: Copy {
+    // GL::FLOAT, GL::UNSIGNED_SHORT, ...
+    const GL_TYPE: u32;
+    const BYTES: usize;
+
+    fn buffer_data<B: RenderBackend>(gl: &B, target: u32, data: &[Self], usage: u32);
+    fn buffer_sub_data<B: RenderBackend>(gl: &B, target: u32, offset: i32, data: &[Self]);
+}
+
+impl BufferData for f32 {
+    const GL_TYPE: u32 = GL::FLOAT;
+    const BYTES: usize = 4;
+
+    fn buffer_data<B: RenderBackend>(gl: &B, target: u32, data: &[f32], usage: u32) {
+        gl.buffer_data_f32(target, data, usage);
+    }
+
+    fn buffer_sub_data<B: RenderBackend>(gl: &B, target: u32, offset: i32, data: &[f32]) {
+        gl.buffer_sub_data_f32(target, offset, data);
+    }
+}
+
+impl BufferData for u16 {
+    const GL_TYPE: u32 = GL::UNSIGNED_SHORT;
+    const BYTES: usize = 2;
+
+    fn buffer_data<B: RenderBackend>(gl: &B, target: u32, data: &[u16], usage: u32) {
+        gl.buffer_data_u16(target, data, usage);
+    }
+
+    fn buffer_sub_data<B: RenderBackend>(gl: &B, target: u32, offset: i32, data: &[u16]) {
+        gl.buffer_sub_data_u16(target, offset, data);
+    }
+}
+
+impl BufferData for u32 {
+    const GL_TYPE: u32 = GL::UNSIGNED_INT;
+    const BYTES: usize = 4;
+
+    fn buffer_data<B: RenderBackend>(gl: &B, target: u32, data: &[u32], usage: u32) {
+        gl.buffer_data_u32(target, data, usage);
+    }
+
+    fn buffer_sub_data<B: RenderBackend>(gl: &B, target: u32, offset: i32, data: &[u32]) {
+        gl.buffer_sub_data_u32(target, offset, data);
+    }
+}
+
+// Per-vertex attribute data, `components` values per vertex
+pub struct VertexBuffer<B: RenderBackend, T: BufferData = f32> {
+    buffer: B::Buffer,
+    components: i32,
+    usage: u32,
+    // Number of values the GPU storage holds
+    len: usize,
+    _data: PhantomData<T>,
+}
+
+impl<B: RenderBackend, T: BufferData> VertexBuffer<B, T> {
+    pub fn new(gl: &B, components: i32, usage: u32) -> Result<Self, Error> {
+        Ok(Self {
+            buffer: gl.create_buffer().ok_or(Error::BufferAllocation)?,
+            components,
+            usage,
+            len: 0,
+            _data: PhantomData,
+        })
+    }
+
+    pub fn with_data(gl: &B, components: i32, data: &[T], usage: u32) -> Result<Self, Error> {
+        let mut buffer = Self::new(gl, components, usage)?;
+        buffer.upload(gl, data);
+        Ok(buffer)
+    }
+
+    // Replaces the contents, reallocating the GPU storage only when the length changes
+    pub fn upload(&mut self, gl: &B, data: &[T]) {
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.buffer));
+        if data.len() == self.len {
+            T::buffer_sub_data(gl, GL::ARRAY_BUFFER, 0, data);
+        } else {
+            T::buffer_data(gl, GL::ARRAY_BUFFER, data, self.usage);
+            self.len = data.len();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.len / self.components as usize
+    }
+
+    // Feeds the attribute at `location` from this buffer, starting at `first_vertex`
+    pub fn bind_attribute(&self, gl: &B, location: u32, first_vertex: usize) {
+        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.buffer));
+        let offset = first_vertex * self.components as usize * T::BYTES;
+        gl.vertex_attrib_pointer(location, self.components, T::GL_TYPE, false, 0, offset as i32);
+        gl.enable_vertex_attrib_array(location);
+    }
+}
+
+// Element indices, u16 or u32 depending on what was uploaded last
+pub struct IndexBuffer<B: RenderBackend> {
+    buffer: B::Buffer,
+    usage: u32,
+    index_type: u32,
+    len: usize,
+}
+
+impl<B: RenderBackend> IndexBuffer<B> {
+    pub fn new(gl: &B, usage: u32) -> Result<Self, Error> {
+        Ok(Self {
+            buffer: gl.create_buffer().ok_or(Error::BufferAllocation)?,
+            usage,
+            index_type: GL::UNSIGNED_SHORT,
+            len: 0,
+        })
+    }
+
+    pub fn with_data(gl: &B, indices: &MeshIndices, usage: u32) -> Result<Self, Error> {
+        let mut buffer = Self::new(gl, usage)?;
+        buffer.upload(gl, indices);
+        Ok(buffer)
+    }
+
+    pub fn upload(&mut self, gl: &B, indices: &MeshIndices) {
+        gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(&self.buffer));
+        match indices {
+            MeshIndices::U16(indices) => u16::buffer_data(gl, GL::ELEMENT_ARRAY_BUFFER, indices, self.usage),
+            MeshIndices::U32(indices) => u32::buffer_data(gl, GL::ELEMENT_ARRAY_BUFFER, indices, self.usage),
+        }
+        self.index_type = indices.gl_type();
+        self.len = indices.len();
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Draws the first `count` indices
+    pub fn draw(&self, gl: &B, mode: u32, count: usize) {
+        gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(&self.buffer));
+        gl.draw_elements(mode, count.min(self.len) as i32, self.index_type, 0);
+    }
+}
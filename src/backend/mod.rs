@@ -0,0 +1,70 @@
+// The subset of the WebGL API used by the programs, so they can draw either
+// into a browser canvas or into the CPU rasterizer in `software`.
+// Method names and arguments follow WebGlRenderingContext, and the GL enums
+// (GL::ARRAY_BUFFER, GL::TRIANGLES, ...) are used on both sides
+mod webgl;
+
+use crate::error::Error;
+
+pub mod buffers;
+pub mod program;
+pub mod software;
+
+// An attribute or uniform the linked program uses, as reported by getActiveAttrib / getActiveUniform
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveVariable {
+    // Without the "[0]" of arrays
+    pub name: String,
+    // GL::FLOAT, GL::FLOAT_VEC4, GL::FLOAT_MAT4, ...
+    pub type_: u32,
+    // Number of elements for arrays, 1 otherwise
+    pub size: i32,
+}
+
+pub trait RenderBackend {
+    type Program;
+    type Buffer;
+    type UniformLocation;
+
+    // The sources go through shaders::preprocessor::preprocess with `defines` first.
+    // `attributes` are bound to locations 0, 1, 2, ... in this order before linking (bindAttribLocation)
+    fn link_program(&self, vert_source: &str, frag_source: &str, defines: &[&str], attributes: &[&str]) -> Result<Self::Program, Error>;
+    fn use_program(&self, program: Option<&Self::Program>);
+    fn get_uniform_location(&self, program: &Self::Program, name: &str) -> Option<Self::UniformLocation>;
+    // None when the vertex shader has no active attribute with this name
+    fn get_attrib_location(&self, program: &Self::Program, name: &str) -> Option<u32>;
+    fn active_attributes(&self, program: &Self::Program) -> Vec<ActiveVariable>;
+    fn active_uniforms(&self, program: &Self::Program) -> Vec<ActiveVariable>;
+
+    fn create_buffer(&self) -> Option<Self::Buffer>;
+    fn bind_buffer(&self, target: u32, buffer: Option<&Self::Buffer>);
+    fn buffer_data_f32(&self, target: u32, data: &[f32], usage: u32);
+    fn buffer_data_u16(&self, target: u32, data: &[u16], usage: u32);
+    fn buffer_data_u32(&self, target: u32, data: &[u32], usage: u32);
+    // Overwrite part of the bound buffer without reallocating it, `offset` is in bytes
+    fn buffer_sub_data_f32(&self, target: u32, offset: i32, data: &[f32]);
+    fn buffer_sub_data_u16(&self, target: u32, offset: i32, data: &[u16]);
+    fn buffer_sub_data_u32(&self, target: u32, offset: i32, data: &[u32]);
+
+    fn vertex_attrib_pointer(&self, index: u32, size: i32, type_: u32, normalized: bool, stride: i32, offset: i32);
+    fn enable_vertex_attrib_array(&self, index: u32);
+
+    fn uniform1f(&self, location: Option<&Self::UniformLocation>, x: f32);
+    fn uniform2f(&self, location: Option<&Self::UniformLocation>, x: f32, y: f32);
+    fn uniform3f(&self, location: Option<&Self::UniformLocation>, x: f32, y: f32, z: f32);
+    fn uniform4f(&self, location: Option<&Self::UniformLocation>, x: f32, y: f32, z: f32, w: f32);
+    fn uniform_matrix4fv(&self, location: Option<&Self::UniformLocation>, transpose: bool, data: &[f32]);
+
+    fn draw_arrays(&self, mode: u32, first: i32, count: i32);
+    // `offset` is in bytes into the bound ELEMENT_ARRAY_BUFFER, like in WebGL
+    fn draw_elements(&self, mode: u32, count: i32, type_: u32, offset: i32);
+    // Turns on GL::UNSIGNED_INT indices for draw_elements, false when they are not
+    // available (WebGL 1 needs the OES_element_index_uint extension)
+    fn enable_element_index_uint(&self) -> bool;
+
+    fn enable(&self, cap: u32);
+    fn blend_func(&self, sfactor: u32, dfactor: u32);
+    fn clear_color(&self, red: f32, green: f32, blue: f32, alpha: f32);
+    fn clear_depth(&self, depth: f32);
+    fn clear(&self, mask: u32);
+}
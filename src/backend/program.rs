@@ -0,0 +1,148 @@
+// A linked program together with what it declares: attributes are bound to locations by
+// name before linking, and the active uniforms are looked up once so the programs can set
+// them by name. A name or type that does not match the shaders is reported as a warning
+// (once per name), where plain WebGL silently ignores the call
+use std::cell::RefCell;
+use std::collections::HashMap;
+use web_sys::WebGlRenderingContext as GL;
+use crate::error::Error;
+use super::{ActiveVariable, RenderBackend};
+
+pub struct ShaderProgram<B: RenderBackend> {
+    program: B::Program,
+    attributes: Vec<ActiveVariable>,
+    uniforms: HashMap<String, (ActiveVariable, B::UniformLocation)>,
+    bound_attributes: Vec<String>,
+    warnings: RefCell<Vec<String>>,
+}
+
+impl<B: RenderBackend> ShaderProgram<B> {
+    // `defines` toggle the #ifdef blocks of the shaders, see shaders::preprocessor.
+    // `attributes` get locations 0, 1, 2, ... in this order
+    pub fn new(gl: &B, vert_source: &str, frag_source: &str, defines: &[&str], attributes: &[&str]) -> Result<Self, Error> {
+        let program = gl.link_program(vert_source, frag_source, defines, attributes)?;
+        let active_attributes = gl.active_attributes(&program);
+        let uniforms = gl
+            .active_uniforms(&program)
+            .into_iter()
+            .filter_map(|uniform| {
+                let location = gl.get_uniform_location(&program, &uniform.name)?;
+                Some((uniform.name.clone(), (uniform, location)))
+            })
+            .collect();
+        let return_var = Self {
+            program,
+            attributes: active_attributes,
+            uniforms,
+            bound_attributes: attributes.iter().map(|name| name.to_string()).collect(),
+            warnings: RefCell::new(Vec::new()),
+        };
+
+        for name in attributes {
+            if !return_var.attributes.iter().any(|attribute| attribute.name == *name) {
+                return_var.warn(format!("Attribute '{}' is not used by the vertex shader", name));
+            }
+        }
+        for attribute in &return_var.attributes {
+            if !attributes.contains(&attribute.name.as_str()) {
+                return_var.warn(format!("Attribute '{}' was not given a location, nothing feeds it", attribute.name));
+            }
+        }
+        Ok(return_var)
+    }
+
+    pub fn use_program(&self, gl: &B) {
+        gl.use_program(Some(&self.program));
+    }
+
+    // Location bound to one of the attributes passed to `new`
+    pub fn attribute_location(&self, name: &str) -> Result<u32, Error> {
+        self.bound_attributes
+            .iter()
+            .position(|bound| bound == name)
+            .map(|location| location as u32)
+            .ok_or_else(|| Error::MissingAttribute(name.to_string()))
+    }
+
+    pub fn active_attributes(&self) -> &[ActiveVariable] {
+        &self.attributes
+    }
+
+    pub fn active_uniforms(&self) -> impl Iterator<Item = &ActiveVariable> {
+        self.uniforms.values().map(|(uniform, _)| uniform)
+    }
+
+    pub fn has_uniform(&self, name: &str) -> bool {
+        self.uniforms.contains_key(name)
+    }
+
+    pub fn set_float(&self, gl: &B, name: &str, x: f32) {
+        gl.uniform1f(self.uniform(name, GL::FLOAT), x);
+    }
+
+    pub fn set_vec2(&self, gl: &B, name: &str, x: f32, y: f32) {
+        gl.uniform2f(self.uniform(name, GL::FLOAT_VEC2), x, y);
+    }
+
+    pub fn set_vec3(&self, gl: &B, name: &str, x: f32, y: f32, z: f32) {
+        gl.uniform3f(self.uniform(name, GL::FLOAT_VEC3), x, y, z);
+    }
+
+    pub fn set_vec4(&self, gl: &B, name: &str, x: f32, y: f32, z: f32, w: f32) {
+        gl.uniform4f(self.uniform(name, GL::FLOAT_VEC4), x, y, z, w);
+    }
+
+    pub fn set_mat4(&self, gl: &B, name: &str, matrix: &[f32; 16]) {
+        gl.uniform_matrix4fv(self.uniform(name, GL::FLOAT_MAT4), false, matrix);
+    }
+
+    // Mismatches found so far
+    pub fn warnings(&self) -> Vec<String> {
+        self.warnings.borrow().clone()
+    }
+
+    fn uniform(&self, name: &str, type_: u32) -> Option<&B::UniformLocation> {
+        match self.uniforms.get(name) {
+            Some((uniform, location)) if uniform.type_ == type_ => Some(location),
+            Some((uniform, _)) => {
+                self.warn(format!(
+                    "Uniform '{}' is a {} in the shaders but was set as a {}",
+                    name, glsl_type_name(uniform.type_), glsl_type_name(type_)
+                ));
+                None
+            }
+            None => {
+                self.warn(format!("Uniform '{}' is not used by the shaders", name));
+                None
+            }
+        }
+    }
+
+    fn warn(&self, message: String) {
+        let mut warnings = self.warnings.borrow_mut();
+        if warnings.contains(&message) {
+            return;
+        }
+        #[cfg(target_arch = "wasm32")]
+        web_sys::console::warn_1(&message.as_str().into());
+        #[cfg(not(target_arch = "wasm32"))]
+        eprintln!("warning: {}", message);
+        warnings.push(message);
+    }
+}
+
+fn glsl_type_name(type_: u32) -> &'static str {
+    match type_ {
+        GL::FLOAT => "float",
+        GL::FLOAT_VEC2 => "vec2",
+        GL::FLOAT_VEC3 => "vec3",
+        GL::FLOAT_VEC4 => "vec4",
+        GL::FLOAT_MAT2 => "mat2",
+        GL::FLOAT_MAT3 => "mat3",
+        GL::FLOAT_MAT4 => "mat4",
+        GL::INT => "int",
+        GL::BOOL => "bool",
+        GL::SAMPLER_2D => "sampler2D",
+        _ => "value of another type",
+    }
+}
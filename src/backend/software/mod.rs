@@ -0,0 +1,678 @@
+// A pure-Rust rasterizer implementing RenderBackend, so programs can be drawn
+// without a browser or GPU (tests, command line tools).
+// Supports what the programs in this crate use: float vertex attributes,
+// u16 and u32 index buffers, triangle primitives, depth testing and alpha blending
+use std::cell::RefCell;
+use std::collections::HashMap;
+use web_sys::WebGlRenderingContext as GL;
+use crate::error::Error;
+use crate::shaders::preprocessor::preprocess;
+use super::{ActiveVariable, RenderBackend};
+
+mod shaders;
+pub use shaders::*;
+
+const MAX_VERTEX_ATTRIBS: usize = 8;
+
+pub struct SoftwareProgram(usize);
+pub struct SoftwareBuffer(usize);
+pub struct SoftwareUniformLocation {
+    program: usize,
+    name: String,
+}
+
+#[derive(Clone, Copy)]
+struct AttribPointer {
+    enabled: bool,
+    buffer: Option<usize>,
+    size: usize,
+    stride: usize,
+    offset: usize,
+}
+
+impl AttribPointer {
+    fn new() -> Self {
+        Self {
+            enabled: false,
+            buffer: None,
+            size: 4,
+            stride: 0,
+            offset: 0,
+        }
+    }
+}
+
+struct ProgramState {
+    shader: Box<dyn SoftwareShader>,
+    declared_uniforms: Vec<ActiveVariable>,
+    declared_attributes: Vec<ActiveVariable>,
+    // Location of each declared attribute
+    attribute_locations: Vec<usize>,
+    uniforms: HashMap<String, UniformValue>,
+}
+
+struct State {
+    width: usize,
+    height: usize,
+    // RGBA in [0, 1], row 0 is the bottom of the image like in WebGL
+    color: Vec<[f32; 4]>,
+    depth: Vec<f32>,
+    clear_color: [f32; 4],
+    clear_depth: f32,
+    blend: bool,
+    blend_src: u32,
+    blend_dst: u32,
+    depth_test: bool,
+    element_index_uint_supported: bool,
+    // Contents stored as raw little-endian bytes, like a GL buffer object
+    buffers: Vec<Vec<u8>>,
+    array_buffer: Option<usize>,
+    element_array_buffer: Option<usize>,
+    attribs: [AttribPointer; MAX_VERTEX_ATTRIBS],
+    programs: Vec<ProgramState>,
+    current_program: Option<usize>,
+}
+
+#[derive(Clone)]
+struct Vertex {
+    // Clip-space position (gl_Position)
+    position: [f32; 4],
+    varyings: Vec<f32>,
+}
+
+pub struct SoftwareBackend {
+    state: RefCell<State>,
+}
+
+impl SoftwareBackend {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            state: RefCell::new(State {
+                width,
+                height,
+                color: vec![[0.; 4]; width * height],
+                depth: vec![1.; width * height],
+                clear_color: [0.; 4],
+                clear_depth: 1.,
+                blend: false,
+                blend_src: GL::ONE,
+                blend_dst: GL::ZERO,
+                depth_test: false,
+                element_index_uint_supported: true,
+                buffers: Vec::new(),
+                array_buffer: None,
+                element_array_buffer: None,
+                attribs: [AttribPointer::new(); MAX_VERTEX_ATTRIBS],
+                programs: Vec::new(),
+                current_program: None,
+            }),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.state.borrow().width
+    }
+
+    pub fn height(&self) -> usize {
+        self.state.borrow().height
+    }
+
+    /// Makes `enable_element_index_uint` fail, like a WebGL 1 device without OES_element_index_uint
+    pub fn set_element_index_uint_supported(&self, supported: bool) {
+        self.state.borrow_mut().element_index_uint_supported = supported;
+    }
+
+    /// RGBA8 pixels, top row first (the order image files use)
+    pub fn read_pixels(&self) -> Vec<u8> {
+        let state = self.state.borrow();
+        let mut pixels = Vec::with_capacity(state.width * state.height * 4);
+        for row in (0..state.height).rev() {
+            for pixel in &state.color[row * state.width..(row + 1) * state.width] {
+                for channel in pixel {
+                    pixels.push((channel.clamp(0., 1.) * 255.).round() as u8);
+                }
+            }
+        }
+        pixels
+    }
+
+    fn draw_vertices(&self, mode: u32, vertex_ids: &[usize]) {
+        let mut state = self.state.borrow_mut();
+        let program_index = match state.current_program {
+            Some(index) => index,
+            None => return,
+        };
+
+        let vertices: Vec<Vertex> = {
+            let program = &state.programs[program_index];
+            let uniforms = Uniforms::new(&program.uniforms);
+            let varying_count = program.shader.varying_count();
+            vertex_ids
+                .iter()
+                .map(|&id| {
+                    let fetched = state.fetch_attributes(id);
+                    let attributes: Vec<[f32; 4]> = program.attribute_locations.iter().map(|&location| fetched[location]).collect();
+                    let mut varyings = vec![0.; varying_count];
+                    let position = program.shader.vertex(&uniforms, &attributes, &mut varyings);
+                    Vertex { position, varyings }
+                })
+                .collect()
+        };
+
+        let triangles: Vec<[usize; 3]> = match mode {
+            GL::TRIANGLES => (0..vertices.len() / 3).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect(),
+            GL::TRIANGLE_STRIP => (2..vertices.len())
+                .map(|i| if i % 2 == 0 { [i - 2, i - 1, i] } else { [i - 1, i - 2, i] })
+                .collect(),
+            GL::TRIANGLE_FAN => (2..vertices.len()).map(|i| [0, i - 1, i]).collect(),
+            // Points and lines are not used by any program
+            _ => Vec::new(),
+        };
+
+        for [a, b, c] in triangles {
+            state.rasterize_triangle(program_index, &vertices[a], &vertices[b], &vertices[c]);
+        }
+    }
+}
+
+impl State {
+    fn set_buffer_data(&mut self, target: u32, bytes: Vec<u8>) {
+        let bound = match target {
+            GL::ARRAY_BUFFER => self.array_buffer,
+            GL::ELEMENT_ARRAY_BUFFER => self.element_array_buffer,
+            _ => None,
+        };
+        if let Some(buffer) = bound {
+            self.buffers[buffer] = bytes;
+        }
+    }
+
+    fn set_buffer_sub_data(&mut self, target: u32, offset: usize, bytes: &[u8]) {
+        let bound = match target {
+            GL::ARRAY_BUFFER => self.array_buffer,
+            GL::ELEMENT_ARRAY_BUFFER => self.element_array_buffer,
+            _ => None,
+        };
+        // Writing past the end is an INVALID_VALUE error in GL, which leaves the buffer untouched
+        if let Some(destination) = bound.and_then(|buffer| self.buffers[buffer].get_mut(offset..offset + bytes.len())) {
+            destination.copy_from_slice(bytes);
+        }
+    }
+
+    fn set_uniform(&mut self, location: Option<&SoftwareUniformLocation>, value: UniformValue) {
+        if let Some(location) = location {
+            self.programs[location.program]
+                .uniforms
+                .insert(location.name.clone(), value);
+        }
+    }
+
+    fn fetch_attributes(&self, vertex_id: usize) -> [[f32; 4]; MAX_VERTEX_ATTRIBS] {
+        // Disabled attributes read as the default generic value (0, 0, 0, 1)
+        let mut attributes = [[0., 0., 0., 1.]; MAX_VERTEX_ATTRIBS];
+        for (attribute, pointer) in attributes.iter_mut().zip(self.attribs.iter()) {
+            let buffer = match (pointer.enabled, pointer.buffer) {
+                (true, Some(buffer)) => &self.buffers[buffer],
+                _ => continue,
+            };
+            let stride = if pointer.stride == 0 { pointer.size * 4 } else { pointer.stride };
+            let start = pointer.offset + vertex_id * stride;
+            for (component, value) in attribute.iter_mut().take(pointer.size).enumerate() {
+                let byte = start + component * 4;
+                if let Some(bytes) = buffer.get(byte..byte + 4) {
+                    *value = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                }
+            }
+        }
+        attributes
+    }
+
+    fn rasterize_triangle(&mut self, program_index: usize, a: &Vertex, b: &Vertex, c: &Vertex) {
+        // Clip against the near plane (z > -w) and w > 0 so the perspective divide is safe.
+        // The other planes are handled by the window bounds and the depth range check
+        let mut polygon = vec![a.clone(), b.clone(), c.clone()];
+        for distance in [|p: &[f32; 4]| p[2] + p[3], |p: &[f32; 4]| p[3] - f32::EPSILON] {
+            polygon = clip_polygon(&polygon, distance);
+        }
+        for i in 2..polygon.len() {
+            self.fill_triangle(program_index, &polygon[0], &polygon[i - 1], &polygon[i]);
+        }
+    }
+
+    fn fill_triangle(&mut self, program_index: usize, a: &Vertex, b: &Vertex, c: &Vertex) {
+        let (width, height) = (self.width as f32, self.height as f32);
+        // Perspective divide and viewport transform to window coordinates
+        let to_window = |v: &Vertex| {
+            let w = v.position[3];
+            [
+                (v.position[0] / w + 1.) * 0.5 * width,
+                (v.position[1] / w + 1.) * 0.5 * height,
+                (v.position[2] / w + 1.) * 0.5,
+                1. / w,
+            ]
+        };
+        let mut window = [to_window(a), to_window(b), to_window(c)];
+        let mut vertices = [a, b, c];
+
+        let edge = |p: [f32; 4], q: [f32; 4], x: f32, y: f32| (q[0] - p[0]) * (y - p[1]) - (q[1] - p[1]) * (x - p[0]);
+        let mut area = edge(window[0], window[1], window[2][0], window[2][1]);
+        if area == 0. {
+            return;
+        }
+        // No face culling, so bring clockwise triangles to counter-clockwise order
+        if area < 0. {
+            window.swap(1, 2);
+            vertices.swap(1, 2);
+            area = -area;
+        }
+
+        // Top-left fill rule so shared edges are not drawn twice
+        let is_top_left = |p: [f32; 4], q: [f32; 4]| {
+            let (dx, dy) = (q[0] - p[0], q[1] - p[1]);
+            dy < 0. || (dy == 0. && dx < 0.)
+        };
+        let edges = [(1, 2), (2, 0), (0, 1)];
+        let top_left = edges.map(|(p, q)| is_top_left(window[p], window[q]));
+
+        let min_x = window.iter().map(|v| v[0]).fold(f32::INFINITY, f32::min).floor().max(0.) as usize;
+        let max_x = window.iter().map(|v| v[0]).fold(f32::NEG_INFINITY, f32::max).ceil().min(width) as usize;
+        let min_y = window.iter().map(|v| v[1]).fold(f32::INFINITY, f32::min).floor().max(0.) as usize;
+        let max_y = window.iter().map(|v| v[1]).fold(f32::NEG_INFINITY, f32::max).ceil().min(height) as usize;
+
+        let program = &self.programs[program_index];
+        let uniforms = Uniforms::new(&program.uniforms);
+        let mut varyings = vec![0.; program.shader.varying_count()];
+
+        for py in min_y..max_y {
+            for px in min_x..max_x {
+                let (x, y) = (px as f32 + 0.5, py as f32 + 0.5);
+                let weights = edges.map(|(p, q)| edge(window[p], window[q], x, y));
+                let inside = weights
+                    .iter()
+                    .zip(top_left.iter())
+                    .all(|(&w, &tl)| w > 0. || (w == 0. && tl));
+                if !inside {
+                    continue;
+                }
+                let barycentric = weights.map(|w| w / area);
+
+                let depth = barycentric[0] * window[0][2] + barycentric[1] * window[1][2] + barycentric[2] * window[2][2];
+                if !(0. ..=1.).contains(&depth) {
+                    continue;
+                }
+                let pixel = py * self.width + px;
+                if self.depth_test && depth >= self.depth[pixel] {
+                    continue;
+                }
+
+                // Perspective-correct interpolation of the varyings
+                let perspective = [
+                    barycentric[0] * window[0][3],
+                    barycentric[1] * window[1][3],
+                    barycentric[2] * window[2][3],
+                ];
+                let one_over_w: f32 = perspective.iter().sum();
+                for (i, varying) in varyings.iter_mut().enumerate() {
+                    *varying = (perspective[0] * vertices[0].varyings[i]
+                        + perspective[1] * vertices[1].varyings[i]
+                        + perspective[2] * vertices[2].varyings[i])
+                        / one_over_w;
+                }
+
+                let fragment = program.shader.fragment(&uniforms, &varyings).map(|v| v.clamp(0., 1.));
+                self.color[pixel] = if self.blend {
+                    blend(fragment, self.color[pixel], self.blend_src, self.blend_dst)
+                } else {
+                    fragment
+                };
+                if self.depth_test {
+                    self.depth[pixel] = depth;
+                }
+            }
+        }
+    }
+}
+
+// Sutherland-Hodgman against a single plane, keeping the side where distance >= 0
+fn clip_polygon(polygon: &[Vertex], distance: fn(&[f32; 4]) -> f32) -> Vec<Vertex> {
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+    for (i, current) in polygon.iter().enumerate() {
+        let next = &polygon[(i + 1) % polygon.len()];
+        let (d_current, d_next) = (distance(&current.position), distance(&next.position));
+        if d_current >= 0. {
+            clipped.push(current.clone());
+        }
+        if (d_current >= 0.) != (d_next >= 0.) {
+            let t = d_current / (d_current - d_next);
+            let lerp = |p: f32, q: f32| p + (q - p) * t;
+            clipped.push(Vertex {
+                position: [0, 1, 2, 3].map(|k| lerp(current.position[k], next.position[k])),
+                varyings: current.varyings.iter().zip(&next.varyings).map(|(&p, &q)| lerp(p, q)).collect(),
+            });
+        }
+    }
+    clipped
+}
+
+fn blend_factor(factor: u32, src: [f32; 4], dst: [f32; 4]) -> [f32; 4] {
+    match factor {
+        GL::ZERO => [0.; 4],
+        GL::SRC_COLOR => src,
+        GL::ONE_MINUS_SRC_COLOR => src.map(|v| 1. - v),
+        GL::DST_COLOR => dst,
+        GL::ONE_MINUS_DST_COLOR => dst.map(|v| 1. - v),
+        GL::SRC_ALPHA => [src[3]; 4],
+        GL::ONE_MINUS_SRC_ALPHA => [1. - src[3]; 4],
+        GL::DST_ALPHA => [dst[3]; 4],
+        GL::ONE_MINUS_DST_ALPHA => [1. - dst[3]; 4],
+        _ => [1.; 4],
+    }
+}
+
+fn blend(src: [f32; 4], dst: [f32; 4], src_factor: u32, dst_factor: u32) -> [f32; 4] {
+    let sf = blend_factor(src_factor, src, dst);
+    let df = blend_factor(dst_factor, src, dst);
+    let mut return_var = [0.; 4];
+    for i in 0..4 {
+        return_var[i] = (src[i] * sf[i] + dst[i] * df[i]).clamp(0., 1.);
+    }
+    return_var
+}
+
+// Variables declared with `qualifier` in a GLSL source, in order, e.g. uProjection of
+// type GL::FLOAT_MAT4 for "uniform mat4 uProjection;"
+// Every declared variable counts as active, there is no compiler to optimize them out
+fn declared_variables(source: &str, qualifier: &str) -> Vec<ActiveVariable> {
+    source
+        .split(';')
+        .map(str::trim)
+        .filter_map(|statement| statement.lines().last())
+        .map(str::trim)
+        .filter(|declaration| declaration.split_whitespace().next() == Some(qualifier))
+        .filter_map(|declaration| {
+            let mut words = declaration.split_whitespace().rev();
+            let name = words.next()?;
+            let type_ = words.next().and_then(glsl_type)?;
+            let (name, size) = match name.split_once('[') {
+                Some((name, size)) => (name, size.trim_end_matches(']').parse().ok()?),
+                None => (name, 1),
+            };
+            Some(ActiveVariable { name: name.to_string(), type_, size })
+        })
+        .collect()
+}
+
+fn glsl_type(name: &str) -> Option<u32> {
+    let return_var = match name {
+        "float" => GL::FLOAT,
+        "vec2" => GL::FLOAT_VEC2,
+        "vec3" => GL::FLOAT_VEC3,
+        "vec4" => GL::FLOAT_VEC4,
+        "mat2" => GL::FLOAT_MAT2,
+        "mat3" => GL::FLOAT_MAT3,
+        "mat4" => GL::FLOAT_MAT4,
+        "int" => GL::INT,
+        "bool" => GL::BOOL,
+        "sampler2D" => GL::SAMPLER_2D,
+        _ => return None,
+    };
+    Some(return_var)
+}
+
+impl RenderBackend for SoftwareBackend {
+    type Program = SoftwareProgram;
+    type Buffer = SoftwareBuffer;
+    type UniformLocation = SoftwareUniformLocation;
+
+    fn link_program(&self, vert_source: &str, frag_source: &str, defines: &[&str], attributes: &[&str]) -> Result<SoftwareProgram, Error> {
+        let shader = builtin_shader(vert_source, frag_source, defines)
+            .ok_or_else(|| Error::Link(String::from("No software implementation for this shader pair")))?;
+        // For the declarations in the included snippets
+        let vert_source = preprocess(vert_source, defines)?;
+        let frag_source = preprocess(frag_source, defines)?;
+        let mut declared_uniforms = declared_variables(&vert_source, "uniform");
+        declared_uniforms.extend(declared_variables(&frag_source, "uniform"));
+
+        // Attributes that were not bound get the locations after the bound ones
+        let declared_attributes = declared_variables(&vert_source, "attribute");
+        let mut next_unbound = attributes.len();
+        let attribute_locations: Vec<usize> = declared_attributes
+            .iter()
+            .map(|declared| {
+                attributes.iter().position(|name| *name == declared.name).unwrap_or_else(|| {
+                    next_unbound += 1;
+                    next_unbound - 1
+                })
+            })
+            .collect();
+        if attribute_locations.iter().any(|&location| location >= MAX_VERTEX_ATTRIBS) {
+            return Err(Error::Link(format!("More than {} vertex attributes", MAX_VERTEX_ATTRIBS)));
+        }
+
+        let mut state = self.state.borrow_mut();
+        state.programs.push(ProgramState {
+            shader,
+            declared_uniforms,
+            declared_attributes,
+            attribute_locations,
+            uniforms: HashMap::new(),
+        });
+        Ok(SoftwareProgram(state.programs.len() - 1))
+    }
+
+    fn use_program(&self, program: Option<&SoftwareProgram>) {
+        self.state.borrow_mut().current_program = program.map(|p| p.0);
+    }
+
+    fn get_uniform_location(&self, program: &SoftwareProgram, name: &str) -> Option<SoftwareUniformLocation> {
+        let state = self.state.borrow();
+        state.programs[program.0]
+            .declared_uniforms
+            .iter()
+            .any(|declared| declared.name == name)
+            .then(|| SoftwareUniformLocation {
+                program: program.0,
+                name: name.to_string(),
+            })
+    }
+
+    fn get_attrib_location(&self, program: &SoftwareProgram, name: &str) -> Option<u32> {
+        let state = self.state.borrow();
+        let program = &state.programs[program.0];
+        program
+            .declared_attributes
+            .iter()
+            .position(|declared| declared.name == name)
+            .map(|index| program.attribute_locations[index] as u32)
+    }
+
+    fn active_attributes(&self, program: &SoftwareProgram) -> Vec<ActiveVariable> {
+        self.state.borrow().programs[program.0].declared_attributes.clone()
+    }
+
+    fn active_uniforms(&self, program: &SoftwareProgram) -> Vec<ActiveVariable> {
+        self.state.borrow().programs[program.0].declared_uniforms.clone()
+    }
+
+    fn create_buffer(&self) -> Option<SoftwareBuffer> {
+        let mut state = self.state.borrow_mut();
+        state.buffers.push(Vec::new());
+        Some(SoftwareBuffer(state.buffers.len() - 1))
+    }
+
+    fn bind_buffer(&self, target: u32, buffer: Option<&SoftwareBuffer>) {
+        let mut state = self.state.borrow_mut();
+        let buffer = buffer.map(|b| b.0);
+        match target {
+            GL::ARRAY_BUFFER => state.array_buffer = buffer,
+            GL::ELEMENT_ARRAY_BUFFER => state.element_array_buffer = buffer,
+            _ => {}
+        }
+    }
+
+    fn buffer_data_f32(&self, target: u32, data: &[f32], _usage: u32) {
+        let bytes = data.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.state.borrow_mut().set_buffer_data(target, bytes);
+    }
+
+    fn buffer_data_u16(&self, target: u32, data: &[u16], _usage: u32) {
+        let bytes = data.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.state.borrow_mut().set_buffer_data(target, bytes);
+    }
+
+    fn buffer_data_u32(&self, target: u32, data: &[u32], _usage: u32) {
+        let bytes = data.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.state.borrow_mut().set_buffer_data(target, bytes);
+    }
+
+    fn buffer_sub_data_f32(&self, target: u32, offset: i32, data: &[f32]) {
+        let bytes: Vec<u8> = data.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.state.borrow_mut().set_buffer_sub_data(target, offset as usize, &bytes);
+    }
+
+    fn buffer_sub_data_u16(&self, target: u32, offset: i32, data: &[u16]) {
+        let bytes: Vec<u8> = data.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.state.borrow_mut().set_buffer_sub_data(target, offset as usize, &bytes);
+    }
+
+    fn buffer_sub_data_u32(&self, target: u32, offset: i32, data: &[u32]) {
+        let bytes: Vec<u8> = data.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.state.borrow_mut().set_buffer_sub_data(target, offset as usize, &bytes);
+    }
+
+    fn vertex_attrib_pointer(&self, index: u32, size: i32, _type: u32, _normalized: bool, stride: i32, offset: i32) {
+        // Only GL::FLOAT attributes are used by the programs
+        // A size outside [1, 4] or a negative stride or offset is an INVALID_VALUE error in GL
+        if !(1..=4).contains(&size) || stride < 0 || offset < 0 {
+            return;
+        }
+        let mut state = self.state.borrow_mut();
+        let buffer = state.array_buffer;
+        // An index past MAX_VERTEX_ATTRIBS is an INVALID_VALUE error in GL
+        let pointer = match state.attribs.get_mut(index as usize) {
+            Some(pointer) => pointer,
+            None => return,
+        };
+        pointer.buffer = buffer;
+        pointer.size = size as usize;
+        pointer.stride = stride as usize;
+        pointer.offset = offset as usize;
+    }
+
+    fn enable_vertex_attrib_array(&self, index: u32) {
+        if let Some(pointer) = self.state.borrow_mut().attribs.get_mut(index as usize) {
+            pointer.enabled = true;
+        }
+    }
+
+    fn uniform1f(&self, location: Option<&SoftwareUniformLocation>, x: f32) {
+        self.state.borrow_mut().set_uniform(location, UniformValue::Float(x));
+    }
+
+    fn uniform2f(&self, location: Option<&SoftwareUniformLocation>, x: f32, y: f32) {
+        self.state.borrow_mut().set_uniform(location, UniformValue::Vec2([x, y]));
+    }
+
+    fn uniform3f(&self, location: Option<&SoftwareUniformLocation>, x: f32, y: f32, z: f32) {
+        self.state.borrow_mut().set_uniform(location, UniformValue::Vec3([x, y, z]));
+    }
+
+    fn uniform4f(&self, location: Option<&SoftwareUniformLocation>, x: f32, y: f32, z: f32, w: f32) {
+        self.state.borrow_mut().set_uniform(location, UniformValue::Vec4([x, y, z, w]));
+    }
+
+    fn uniform_matrix4fv(&self, location: Option<&SoftwareUniformLocation>, _transpose: bool, data: &[f32]) {
+        // Too short an array is an INVALID_VALUE error in GL
+        if data.len() < 16 {
+            return;
+        }
+        let mut matrix = [0.; 16];
+        matrix.copy_from_slice(&data[..16]);
+        self.state.borrow_mut().set_uniform(location, UniformValue::Mat4(matrix));
+    }
+
+    fn draw_arrays(&self, mode: u32, first: i32, count: i32) {
+        // A negative first or count is an INVALID_VALUE error in GL
+        if first < 0 || count < 0 {
+            return;
+        }
+        let end = match first.checked_add(count) {
+            Some(end) => end,
+            None => return,
+        };
+        let vertex_ids: Vec<usize> = (first as usize..end as usize).collect();
+        self.draw_vertices(mode, &vertex_ids);
+    }
+
+    fn draw_elements(&self, mode: u32, count: i32, type_: u32, offset: i32) {
+        // A negative count or offset is an INVALID_VALUE error in GL
+        if count < 0 || offset < 0 {
+            return;
+        }
+        let vertex_ids: Vec<usize> = {
+            let state = self.state.borrow();
+            let buffer = match state.element_array_buffer {
+                Some(buffer) => &state.buffers[buffer],
+                None => return,
+            };
+            // An offset past the end of the buffer is an INVALID_OPERATION error in GL
+            let indices = match buffer.get(offset as usize..) {
+                Some(indices) => indices,
+                None => return,
+            };
+            match type_ {
+                GL::UNSIGNED_SHORT => indices
+                    .chunks_exact(2)
+                    .take(count as usize)
+                    .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+                    .collect(),
+                GL::UNSIGNED_INT if state.element_index_uint_supported => indices
+                    .chunks_exact(4)
+                    .take(count as usize)
+                    .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+                    .collect(),
+                _ => return,
+            }
+        };
+        self.draw_vertices(mode, &vertex_ids);
+    }
+
+    fn enable_element_index_uint(&self) -> bool {
+        self.state.borrow().element_index_uint_supported
+    }
+
+    fn enable(&self, cap: u32) {
+        let mut state = self.state.borrow_mut();
+        match cap {
+            GL::BLEND => state.blend = true,
+            GL::DEPTH_TEST => state.depth_test = true,
+            _ => {}
+        }
+    }
+
+    fn blend_func(&self, sfactor: u32, dfactor: u32) {
+        let mut state = self.state.borrow_mut();
+        state.blend_src = sfactor;
+        state.blend_dst = dfactor;
+    }
+
+    fn clear_color(&self, red: f32, green: f32, blue: f32, alpha: f32) {
+        self.state.borrow_mut().clear_color = [red, green, blue, alpha];
+    }
+
+    fn clear_depth(&self, depth: f32) {
+        self.state.borrow_mut().clear_depth = depth;
+    }
+
+    fn clear(&self, mask: u32) {
+        let mut state = self.state.borrow_mut();
+        if mask & GL::COLOR_BUFFER_BIT != 0 {
+            let clear_color = state.clear_color;
+            state.color.fill(clear_color);
+        }
+        if mask & GL::DEPTH_BUFFER_BIT != 0 {
+            let clear_depth = state.clear_depth;
+            state.depth.fill(clear_depth);
+        }
+    }
+}
@@ -0,0 +1,458 @@
+// CPU ports of the GLSL programs in crate::shaders
+// The software backend cannot run GLSL, so every vertex/fragment shader pair
+// that should be drawable headless needs a matching SoftwareShader here.
+// PORTED_SOURCES pins the GLSL each port was written against, so tests/golden.rs
+// fails on any shader edit until the port, the hash and the goldens are updated
+use std::collections::HashMap;
+use crate::programs::BuiltinSurface;
+use crate::shaders;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UniformValue {
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    Mat4([f32; 16]),
+}
+
+/// Uniform values of the program being drawn, looked up by their GLSL name
+/// Unset uniforms read as zero, like in WebGL
+pub struct Uniforms<'a> {
+    values: &'a HashMap<String, UniformValue>,
+}
+
+impl<'a> Uniforms<'a> {
+    pub fn new(values: &'a HashMap<String, UniformValue>) -> Self {
+        Self { values }
+    }
+
+    pub fn float(&self, name: &str) -> f32 {
+        match self.values.get(name) {
+            Some(UniformValue::Float(v)) => *v,
+            _ => 0.,
+        }
+    }
+
+    pub fn vec2(&self, name: &str) -> [f32; 2] {
+        match self.values.get(name) {
+            Some(UniformValue::Vec2(v)) => *v,
+            _ => [0.; 2],
+        }
+    }
+
+    pub fn vec3(&self, name: &str) -> [f32; 3] {
+        match self.values.get(name) {
+            Some(UniformValue::Vec3(v)) => *v,
+            _ => [0.; 3],
+        }
+    }
+
+    pub fn vec4(&self, name: &str) -> [f32; 4] {
+        match self.values.get(name) {
+            Some(UniformValue::Vec4(v)) => *v,
+            _ => [0.; 4],
+        }
+    }
+
+    pub fn mat4(&self, name: &str) -> [f32; 16] {
+        match self.values.get(name) {
+            Some(UniformValue::Mat4(m)) => *m,
+            _ => [0.; 16],
+        }
+    }
+}
+
+pub trait SoftwareShader {
+    // Number of floats passed from the vertex stage to the fragment stage
+    fn varying_count(&self) -> usize;
+    // `attributes` are in the order the vertex shader declares them, returns gl_Position
+    fn vertex(&self, uniforms: &Uniforms, attributes: &[[f32; 4]], varyings: &mut [f32]) -> [f32; 4];
+    // Returns gl_FragColor
+    fn fragment(&self, uniforms: &Uniforms, varyings: &[f32]) -> [f32; 4];
+}
+
+/// Looks up the CPU implementation of one of the built-in shader pairs, given the sources
+/// before preprocessing and the defines they are compiled with
+pub fn builtin_shader(vert_source: &str, frag_source: &str, defines: &[&str]) -> Option<Box<dyn SoftwareShader>> {
+    use shaders::vertex as v;
+    use shaders::fragment as f;
+
+    if vert_source == v::color_2d::SHADER && frag_source == f::color_2d::SHADER {
+        return Some(Box::new(Color2DShader));
+    } else if vert_source == v::color_2d_gradient::SHADER && frag_source == f::varying_color_from_vertex::SHADER {
+        return Some(Box::new(Color2DGradientShader));
+    }
+
+    // Compiled with PER_PIXEL_LIGHTING, the graph shaders only write the varyings of lit_surface
+    let features = GraphFeatures::from_defines(defines);
+    let graph_fragment = match features.lighting {
+        GraphLighting::Vertex => f::varying_color_from_vertex::SHADER,
+        GraphLighting::Phong | GraphLighting::BlinnPhong => f::lit_surface::SHADER,
+    };
+    if vert_source == v::graph_3d::SHADER && frag_source == graph_fragment {
+        Some(Box::new(Graph3DShader(features)))
+    } else if vert_source == v::graph_3d_surface::SHADER && frag_source == graph_fragment {
+        Some(Box::new(Graph3DSurfaceShader(features)))
+    } else {
+        None
+    }
+}
+
+/// A shader pair and defines with a CPU port, and the FNV-1a 64 hash of the
+/// preprocessed vertex source followed by the preprocessed fragment source
+pub struct PortedSource {
+    pub vertex: &'static str,
+    pub fragment: &'static str,
+    pub defines: &'static [&'static str],
+    pub hash: u64,
+}
+
+// Defines are in the order Graph3D passes them
+pub const PORTED_SOURCES: &[PortedSource] = {
+    use shaders::vertex as v;
+    use shaders::fragment as f;
+    &[
+        PortedSource { vertex: v::color_2d::SHADER, fragment: f::color_2d::SHADER, defines: &[], hash: 0xd913185c3fee8e86 },
+        PortedSource { vertex: v::color_2d_gradient::SHADER, fragment: f::varying_color_from_vertex::SHADER, defines: &[], hash: 0x5fb8a5a8c17fba42 },
+        PortedSource { vertex: v::graph_3d::SHADER, fragment: f::varying_color_from_vertex::SHADER, defines: &[], hash: 0x4412708f22d44ddd },
+        PortedSource { vertex: v::graph_3d::SHADER, fragment: f::varying_color_from_vertex::SHADER, defines: &["FOG"], hash: 0x84cec2ad38824cd9 },
+        PortedSource { vertex: v::graph_3d::SHADER, fragment: f::varying_color_from_vertex::SHADER, defines: &["COLORMAP"], hash: 0xad6b6117f87ce58d },
+        PortedSource { vertex: v::graph_3d::SHADER, fragment: f::varying_color_from_vertex::SHADER, defines: &["COLORMAP", "FOG"], hash: 0x43cff4411551b5b9 },
+        PortedSource { vertex: v::graph_3d::SHADER, fragment: f::lit_surface::SHADER, defines: &["PER_PIXEL_LIGHTING"], hash: 0x55a09c870c9321ca },
+        PortedSource { vertex: v::graph_3d::SHADER, fragment: f::lit_surface::SHADER, defines: &["FOG", "PER_PIXEL_LIGHTING"], hash: 0xfcb286b49c3c3582 },
+        PortedSource { vertex: v::graph_3d::SHADER, fragment: f::lit_surface::SHADER, defines: &["COLORMAP", "PER_PIXEL_LIGHTING"], hash: 0x65b0c37f3a95b7b2 },
+        PortedSource { vertex: v::graph_3d::SHADER, fragment: f::lit_surface::SHADER, defines: &["COLORMAP", "FOG", "PER_PIXEL_LIGHTING"], hash: 0x5aab321ab6c1943a },
+        PortedSource { vertex: v::graph_3d::SHADER, fragment: f::lit_surface::SHADER, defines: &["PER_PIXEL_LIGHTING", "BLINN_PHONG"], hash: 0xcedabd359ec7290a },
+        PortedSource { vertex: v::graph_3d::SHADER, fragment: f::lit_surface::SHADER, defines: &["FOG", "PER_PIXEL_LIGHTING", "BLINN_PHONG"], hash: 0xbbcc5a66b1d72772 },
+        PortedSource { vertex: v::graph_3d::SHADER, fragment: f::lit_surface::SHADER, defines: &["COLORMAP", "PER_PIXEL_LIGHTING", "BLINN_PHONG"], hash: 0x0d5e4df18c9f1476 },
+        PortedSource { vertex: v::graph_3d::SHADER, fragment: f::lit_surface::SHADER, defines: &["COLORMAP", "FOG", "PER_PIXEL_LIGHTING", "BLINN_PHONG"], hash: 0x3ae0e6af78655d4e },
+        PortedSource { vertex: v::graph_3d_surface::SHADER, fragment: f::varying_color_from_vertex::SHADER, defines: &[], hash: 0xcad67d9d2df646ab },
+        PortedSource { vertex: v::graph_3d_surface::SHADER, fragment: f::varying_color_from_vertex::SHADER, defines: &["FOG"], hash: 0x22122fa67fb0226b },
+        PortedSource { vertex: v::graph_3d_surface::SHADER, fragment: f::varying_color_from_vertex::SHADER, defines: &["COLORMAP"], hash: 0x4f094b0888d17573 },
+        PortedSource { vertex: v::graph_3d_surface::SHADER, fragment: f::varying_color_from_vertex::SHADER, defines: &["COLORMAP", "FOG"], hash: 0xee6c611d1d250cf3 },
+        PortedSource { vertex: v::graph_3d_surface::SHADER, fragment: f::lit_surface::SHADER, defines: &["PER_PIXEL_LIGHTING"], hash: 0x45d1b965ed87fa98 },
+        PortedSource { vertex: v::graph_3d_surface::SHADER, fragment: f::lit_surface::SHADER, defines: &["FOG", "PER_PIXEL_LIGHTING"], hash: 0x42a8628a610d8604 },
+        PortedSource { vertex: v::graph_3d_surface::SHADER, fragment: f::lit_surface::SHADER, defines: &["COLORMAP", "PER_PIXEL_LIGHTING"], hash: 0x5f9aa380a311ad54 },
+        PortedSource { vertex: v::graph_3d_surface::SHADER, fragment: f::lit_surface::SHADER, defines: &["COLORMAP", "FOG", "PER_PIXEL_LIGHTING"], hash: 0xad4168fdf65efa90 },
+        PortedSource { vertex: v::graph_3d_surface::SHADER, fragment: f::lit_surface::SHADER, defines: &["PER_PIXEL_LIGHTING", "BLINN_PHONG"], hash: 0x76025e12d482246c },
+        PortedSource { vertex: v::graph_3d_surface::SHADER, fragment: f::lit_surface::SHADER, defines: &["FOG", "PER_PIXEL_LIGHTING", "BLINN_PHONG"], hash: 0x67b6d2ab409c0788 },
+        PortedSource { vertex: v::graph_3d_surface::SHADER, fragment: f::lit_surface::SHADER, defines: &["COLORMAP", "PER_PIXEL_LIGHTING", "BLINN_PHONG"], hash: 0x011f4eeece7a00d4 },
+        PortedSource { vertex: v::graph_3d_surface::SHADER, fragment: f::lit_surface::SHADER, defines: &["COLORMAP", "FOG", "PER_PIXEL_LIGHTING", "BLINN_PHONG"], hash: 0xc8abe040b91f6330 },
+    ]
+};
+
+// Column-major mat4 * vec4, matching GLSL for matrices uploaded with transpose = false
+pub fn mat4_mul_vec4(m: &[f32; 16], v: [f32; 4]) -> [f32; 4] {
+    let mut return_var = [0.; 4];
+    for (row, out) in return_var.iter_mut().enumerate() {
+        *out = m[row] * v[0] + m[4 + row] * v[1] + m[8 + row] * v[2] + m[12 + row] * v[3];
+    }
+    return_var
+}
+
+fn normalize3(v: [f32; 3]) -> [f32; 3] {
+    let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    [v[0] / length, v[1] / length, v[2] / length]
+}
+
+fn dot3(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+// shaders::vertex::color_2d + shaders::fragment::color_2d
+struct Color2DShader;
+
+impl SoftwareShader for Color2DShader {
+    fn varying_count(&self) -> usize {
+        0
+    }
+
+    fn vertex(&self, uniforms: &Uniforms, attributes: &[[f32; 4]], _varyings: &mut [f32]) -> [f32; 4] {
+        mat4_mul_vec4(&uniforms.mat4("uTransform"), attributes[0])
+    }
+
+    fn fragment(&self, uniforms: &Uniforms, _varyings: &[f32]) -> [f32; 4] {
+        let color = uniforms.vec4("uColor");
+        [color[0], color[1], color[2], color[3] * uniforms.float("uOpacity")]
+    }
+}
+
+// shaders::vertex::color_2d_gradient + shaders::fragment::varying_color_from_vertex
+struct Color2DGradientShader;
+
+impl SoftwareShader for Color2DGradientShader {
+    fn varying_count(&self) -> usize {
+        4
+    }
+
+    fn vertex(&self, uniforms: &Uniforms, attributes: &[[f32; 4]], varyings: &mut [f32]) -> [f32; 4] {
+        // vColor = aColor
+        varyings.copy_from_slice(&attributes[1]);
+        mat4_mul_vec4(&uniforms.mat4("uTransform"), attributes[0])
+    }
+
+    fn fragment(&self, uniforms: &Uniforms, varyings: &[f32]) -> [f32; 4] {
+        varying_color_from_vertex(uniforms, varyings)
+    }
+}
+
+// PER_PIXEL_LIGHTING and BLINN_PHONG
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GraphLighting {
+    Vertex,
+    Phong,
+    BlinnPhong,
+}
+
+// The #ifdef blocks of the graph_3d shaders
+#[derive(Debug, Clone, Copy)]
+struct GraphFeatures {
+    colormap: bool,
+    fog: bool,
+    lighting: GraphLighting,
+}
+
+impl GraphFeatures {
+    fn from_defines(defines: &[&str]) -> Self {
+        let lighting = if !defines.contains(&"PER_PIXEL_LIGHTING") {
+            GraphLighting::Vertex
+        } else if defines.contains(&"BLINN_PHONG") {
+            GraphLighting::BlinnPhong
+        } else {
+            GraphLighting::Phong
+        };
+        Self {
+            colormap: defines.contains(&"COLORMAP"),
+            fog: defines.contains(&"FOG"),
+            lighting,
+        }
+    }
+
+    // vColor, or vNormal, vBaseColor and vFogDepth with per-pixel lighting
+    fn varying_count(&self) -> usize {
+        match self.lighting {
+            GraphLighting::Vertex => 4,
+            GraphLighting::Phong | GraphLighting::BlinnPhong => 7,
+        }
+    }
+
+    // Varyings of a graph vertex at display coordinates `position`
+    fn shade(&self, uniforms: &Uniforms, position: [f32; 3], normal: [f32; 3], varyings: &mut [f32]) {
+        let transformed_normal = mat4_mul_vec4(
+            &uniforms.mat4("uNormalsRotation"),
+            [normal[0], normal[1], normal[2], 1.0],
+        );
+        let transformed_normal = [transformed_normal[0], transformed_normal[1], transformed_normal[2]];
+        let base_color = if self.colormap {
+            colormap(position[1] * 0.5 + 0.5)
+        } else {
+            [0.5, 0.5, 0.8]
+        };
+        let rotated = mat4_mul_vec4(&uniforms.mat4("uNormalsRotation"), [position[0], position[1], position[2], 1.0]);
+        let fog_depth = -rotated[2];
+
+        if self.lighting != GraphLighting::Vertex {
+            varyings[..3].copy_from_slice(&transformed_normal);
+            varyings[3..6].copy_from_slice(&base_color);
+            varyings[6] = fog_depth;
+            return;
+        }
+        let lighting = lighting(uniforms, transformed_normal);
+        let mut color = [0.; 3];
+        for i in 0..3 {
+            color[i] = base_color[i] * lighting[i];
+        }
+        if self.fog {
+            color = apply_fog(uniforms, color, fog_depth);
+        }
+        varyings[..3].copy_from_slice(&color);
+        varyings[3] = 1.0;
+    }
+
+    fn fragment(&self, uniforms: &Uniforms, varyings: &[f32]) -> [f32; 4] {
+        if self.lighting == GraphLighting::Vertex {
+            return varying_color_from_vertex(uniforms, varyings);
+        }
+        lit_surface(self, uniforms, varyings)
+    }
+}
+
+// shaders::vertex::graph_3d + shaders::fragment::varying_color_from_vertex or lit_surface
+struct Graph3DShader(GraphFeatures);
+
+impl SoftwareShader for Graph3DShader {
+    fn varying_count(&self) -> usize {
+        self.0.varying_count()
+    }
+
+    fn vertex(&self, uniforms: &Uniforms, attributes: &[[f32; 4]], varyings: &mut [f32]) -> [f32; 4] {
+        let position = attributes[0];
+        let y = attributes[1][0];
+        let normal = attributes[2];
+        self.0.shade(uniforms, [position[0], y, position[2]], [normal[0], normal[1], normal[2]], varyings);
+
+        mat4_mul_vec4(&uniforms.mat4("uProjection"), [position[0], y, position[2], 1.0])
+    }
+
+    fn fragment(&self, uniforms: &Uniforms, varyings: &[f32]) -> [f32; 4] {
+        self.0.fragment(uniforms, varyings)
+    }
+}
+
+// shaders::vertex::graph_3d_surface + shaders::fragment::varying_color_from_vertex or lit_surface
+/// Height of a built-in surface as the port of graph_3d_surface computes it, for
+/// checking the shader against BuiltinSurface::expression
+pub fn builtin_surface_height(surface: BuiltinSurface, x: f32, z: f32, time: f32) -> f32 {
+    surface_height(surface.shader_index(), time, x, z)
+}
+
+// The surface function of graph_3d_surface, `surface` is the value of uSurface
+fn surface_height(surface: f32, time: f32, x: f32, z: f32) -> f32 {
+    if surface < 0.5 {
+        0.15 * ((x * x + z * z).sqrt() + time * 0.001).sin()
+    } else if surface < 1.5 {
+        0.2 * (x + time * 0.001).sin() * z.cos()
+    } else {
+        0.02 * (x * x - z * z)
+    }
+}
+
+struct Graph3DSurfaceShader(GraphFeatures);
+
+impl Graph3DSurfaceShader {
+    fn surface(uniforms: &Uniforms, x: f32, z: f32) -> f32 {
+        surface_height(uniforms.float("uSurface"), uniforms.float("uTime"), x, z)
+    }
+
+    fn display_height(uniforms: &Uniforms, x: f32, z: f32) -> f32 {
+        let domain = uniforms.vec4("uDomain");
+        let y_range = uniforms.vec2("uYRange");
+        let data_x = domain[0] + (x * 0.5 + 0.5) * (domain[1] - domain[0]);
+        let data_z = domain[2] + (z * 0.5 + 0.5) * (domain[3] - domain[2]);
+        2.0 * (Self::surface(uniforms, data_x, data_z) - y_range[0]) / (y_range[1] - y_range[0]) - 1.0
+    }
+}
+
+impl SoftwareShader for Graph3DSurfaceShader {
+    fn varying_count(&self) -> usize {
+        self.0.varying_count()
+    }
+
+    fn vertex(&self, uniforms: &Uniforms, attributes: &[[f32; 4]], varyings: &mut [f32]) -> [f32; 4] {
+        let position = attributes[0];
+        let (x, z) = (position[0], position[2]);
+        let y = Self::display_height(uniforms, x, z);
+
+        let grid_step = uniforms.vec2("uGridStep");
+        let slope_x = (Self::display_height(uniforms, x + grid_step[0], z)
+            - Self::display_height(uniforms, x - grid_step[0], z)) / (2.0 * grid_step[0]);
+        let slope_z = (Self::display_height(uniforms, x, z + grid_step[1])
+            - Self::display_height(uniforms, x, z - grid_step[1])) / (2.0 * grid_step[1]);
+        let normal = normalize3([-slope_x, 1.0, -slope_z]);
+        self.0.shade(uniforms, [x, y, z], normal, varyings);
+
+        mat4_mul_vec4(&uniforms.mat4("uProjection"), [x, y, z, 1.0])
+    }
+
+    fn fragment(&self, uniforms: &Uniforms, varyings: &[f32]) -> [f32; 4] {
+        self.0.fragment(uniforms, varyings)
+    }
+}
+
+// shaders::include::lighting
+fn lighting(uniforms: &Uniforms, normal: [f32; 3]) -> [f32; 3] {
+    let light_color = uniforms.vec3("uLightColor");
+    let ambient = uniforms.vec3("uAmbientLight");
+    let directional = dot3(normal, normalize3(uniforms.vec3("uLightDirection"))).max(0.0);
+    let diffuse = uniforms.float("uDiffuse") * directional;
+    [
+        ambient[0] + light_color[0] * diffuse,
+        ambient[1] + light_color[1] * diffuse,
+        ambient[2] + light_color[2] * diffuse,
+    ]
+}
+
+// shaders::include::lighting, `blinn_phong` is the BLINN_PHONG define
+fn specular_lighting(uniforms: &Uniforms, normal: [f32; 3], blinn_phong: bool) -> [f32; 3] {
+    let light_direction = normalize3(uniforms.vec3("uLightDirection"));
+    let view_direction = [0.0, 0.0, 1.0];
+    let n_dot_l = dot3(normal, light_direction);
+    if n_dot_l <= 0.0 {
+        return [0.; 3];
+    }
+    let specular = if blinn_phong {
+        let halfway = normalize3([
+            light_direction[0] + view_direction[0],
+            light_direction[1] + view_direction[1],
+            light_direction[2] + view_direction[2],
+        ]);
+        dot3(normal, halfway).max(0.0)
+    } else {
+        // reflect(-L, N) = -L + 2 (N.L) N
+        let reflected = [
+            2.0 * n_dot_l * normal[0] - light_direction[0],
+            2.0 * n_dot_l * normal[1] - light_direction[1],
+            2.0 * n_dot_l * normal[2] - light_direction[2],
+        ];
+        dot3(reflected, view_direction).max(0.0)
+    };
+    let strength = uniforms.float("uSpecular") * specular.powf(uniforms.float("uShininess"));
+    let light_color = uniforms.vec3("uLightColor");
+    [light_color[0] * strength, light_color[1] * strength, light_color[2] * strength]
+}
+
+// shaders::include::colormap
+fn colormap(t: f32) -> [f32; 3] {
+    const COEFFICIENTS: [[f32; 3]; 7] = [
+        [0.2777, 0.0054, 0.3341],
+        [0.1051, 1.4046, 1.3846],
+        [-0.3309, 0.2148, 0.0951],
+        [-4.6342, -5.7991, -19.3324],
+        [6.2283, 14.1799, 56.6906],
+        [4.7764, -13.7451, -65.3530],
+        [-5.4355, 4.6459, 26.3124],
+    ];
+    let x = t.clamp(0.0, 1.0);
+    let mut return_var = [0.; 3];
+    for i in 0..3 {
+        return_var[i] = COEFFICIENTS.iter().rev().fold(0.0, |sum, c| sum * x + c[i]);
+    }
+    return_var
+}
+
+// shaders::include::fog
+fn apply_fog(uniforms: &Uniforms, color: [f32; 3], depth: f32) -> [f32; 3] {
+    let fog_color = uniforms.vec3("uFogColor");
+    let amount = 0.85 * smoothstep(-0.5, 1.25, depth);
+    let mut return_var = [0.; 3];
+    for i in 0..3 {
+        return_var[i] = color[i] + (fog_color[i] - color[i]) * amount;
+    }
+    return_var
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// shaders::fragment::lit_surface, with the varyings written by GraphFeatures::shade
+fn lit_surface(features: &GraphFeatures, uniforms: &Uniforms, varyings: &[f32]) -> [f32; 4] {
+    let normal = normalize3([varyings[0], varyings[1], varyings[2]]);
+    let lighting = lighting(uniforms, normal);
+    let specular = specular_lighting(uniforms, normal, features.lighting == GraphLighting::BlinnPhong);
+    let mut color = [0.; 3];
+    for i in 0..3 {
+        color[i] = varyings[3 + i] * lighting[i] + specular[i];
+    }
+    if features.fog {
+        color = apply_fog(uniforms, color, varyings[6]);
+    }
+    [color[0], color[1], color[2], uniforms.float("uOpacity")]
+}
+
+// shaders::fragment::varying_color_from_vertex
+fn varying_color_from_vertex(uniforms: &Uniforms, varyings: &[f32]) -> [f32; 4] {
+    [varyings[0], varyings[1], varyings[2], varyings[3] * uniforms.float("uOpacity")]
+}
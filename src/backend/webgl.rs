@@ -0,0 +1,154 @@
+use web_sys::WebGlRenderingContext as GL;
+use web_sys::*;
+use crate::common_funcs as cf;
+use crate::error::Error;
+use super::{ActiveVariable, RenderBackend};
+
+impl RenderBackend for WebGlRenderingContext {
+    type Program = WebGlProgram;
+    type Buffer = WebGlBuffer;
+    type UniformLocation = WebGlUniformLocation;
+
+    fn link_program(&self, vert_source: &str, frag_source: &str, defines: &[&str], attributes: &[&str]) -> Result<WebGlProgram, Error> {
+        cf::link_program(self, vert_source, frag_source, defines, attributes)
+    }
+
+    fn use_program(&self, program: Option<&WebGlProgram>) {
+        GL::use_program(self, program);
+    }
+
+    fn get_uniform_location(&self, program: &WebGlProgram, name: &str) -> Option<WebGlUniformLocation> {
+        GL::get_uniform_location(self, program, name)
+    }
+
+    fn get_attrib_location(&self, program: &WebGlProgram, name: &str) -> Option<u32> {
+        // -1 when there is no such attribute
+        u32::try_from(GL::get_attrib_location(self, program, name)).ok()
+    }
+
+    fn active_attributes(&self, program: &WebGlProgram) -> Vec<ActiveVariable> {
+        let count = self.get_program_parameter(program, GL::ACTIVE_ATTRIBUTES).as_f64().unwrap_or(0.) as u32;
+        (0..count)
+            .filter_map(|index| self.get_active_attrib(program, index))
+            .map(active_variable)
+            .collect()
+    }
+
+    fn active_uniforms(&self, program: &WebGlProgram) -> Vec<ActiveVariable> {
+        let count = self.get_program_parameter(program, GL::ACTIVE_UNIFORMS).as_f64().unwrap_or(0.) as u32;
+        (0..count)
+            .filter_map(|index| self.get_active_uniform(program, index))
+            .map(active_variable)
+            .collect()
+    }
+
+    fn create_buffer(&self) -> Option<WebGlBuffer> {
+        GL::create_buffer(self)
+    }
+
+    fn bind_buffer(&self, target: u32, buffer: Option<&WebGlBuffer>) {
+        GL::bind_buffer(self, target, buffer);
+    }
+
+    // The typed arrays are views into the wasm memory, which are invalidated when it grows.
+    // Safety: each view is passed straight to the GL call, nothing can allocate in between
+    fn buffer_data_f32(&self, target: u32, data: &[f32], usage: u32) {
+        let view = unsafe { js_sys::Float32Array::view(data) };
+        self.buffer_data_with_array_buffer_view(target, &view, usage);
+    }
+
+    fn buffer_data_u16(&self, target: u32, data: &[u16], usage: u32) {
+        let view = unsafe { js_sys::Uint16Array::view(data) };
+        self.buffer_data_with_array_buffer_view(target, &view, usage);
+    }
+
+    fn buffer_data_u32(&self, target: u32, data: &[u32], usage: u32) {
+        let view = unsafe { js_sys::Uint32Array::view(data) };
+        self.buffer_data_with_array_buffer_view(target, &view, usage);
+    }
+
+    fn buffer_sub_data_f32(&self, target: u32, offset: i32, data: &[f32]) {
+        let view = unsafe { js_sys::Float32Array::view(data) };
+        self.buffer_sub_data_with_i32_and_array_buffer_view(target, offset, &view);
+    }
+
+    fn buffer_sub_data_u16(&self, target: u32, offset: i32, data: &[u16]) {
+        let view = unsafe { js_sys::Uint16Array::view(data) };
+        self.buffer_sub_data_with_i32_and_array_buffer_view(target, offset, &view);
+    }
+
+    fn buffer_sub_data_u32(&self, target: u32, offset: i32, data: &[u32]) {
+        let view = unsafe { js_sys::Uint32Array::view(data) };
+        self.buffer_sub_data_with_i32_and_array_buffer_view(target, offset, &view);
+    }
+
+    fn vertex_attrib_pointer(&self, index: u32, size: i32, type_: u32, normalized: bool, stride: i32, offset: i32) {
+        self.vertex_attrib_pointer_with_i32(index, size, type_, normalized, stride, offset);
+    }
+
+    fn enable_vertex_attrib_array(&self, index: u32) {
+        GL::enable_vertex_attrib_array(self, index);
+    }
+
+    fn uniform1f(&self, location: Option<&WebGlUniformLocation>, x: f32) {
+        GL::uniform1f(self, location, x);
+    }
+
+    fn uniform2f(&self, location: Option<&WebGlUniformLocation>, x: f32, y: f32) {
+        GL::uniform2f(self, location, x, y);
+    }
+
+    fn uniform3f(&self, location: Option<&WebGlUniformLocation>, x: f32, y: f32, z: f32) {
+        GL::uniform3f(self, location, x, y, z);
+    }
+
+    fn uniform4f(&self, location: Option<&WebGlUniformLocation>, x: f32, y: f32, z: f32, w: f32) {
+        GL::uniform4f(self, location, x, y, z, w);
+    }
+
+    fn uniform_matrix4fv(&self, location: Option<&WebGlUniformLocation>, transpose: bool, data: &[f32]) {
+        self.uniform_matrix4fv_with_f32_array(location, transpose, data);
+    }
+
+    fn draw_arrays(&self, mode: u32, first: i32, count: i32) {
+        GL::draw_arrays(self, mode, first, count);
+    }
+
+    fn draw_elements(&self, mode: u32, count: i32, type_: u32, offset: i32) {
+        self.draw_elements_with_i32(mode, count, type_, offset);
+    }
+
+    fn enable_element_index_uint(&self) -> bool {
+        // Getting an extension is what enables it
+        matches!(self.get_extension("OES_element_index_uint"), Ok(Some(_)))
+    }
+
+    fn enable(&self, cap: u32) {
+        GL::enable(self, cap);
+    }
+
+    fn blend_func(&self, sfactor: u32, dfactor: u32) {
+        GL::blend_func(self, sfactor, dfactor);
+    }
+
+    fn clear_color(&self, red: f32, green: f32, blue: f32, alpha: f32) {
+        GL::clear_color(self, red, green, blue, alpha);
+    }
+
+    fn clear_depth(&self, depth: f32) {
+        GL::clear_depth(self, depth);
+    }
+
+    fn clear(&self, mask: u32) {
+        GL::clear(self, mask);
+    }
+}
+
+fn active_variable(info: WebGlActiveInfo) -> ActiveVariable {
+    let name = info.name();
+    ActiveVariable {
+        name: name.strip_suffix("[0]").unwrap_or(&name).to_string(),
+        type_: info.type_(),
+        size: info.size(),
+    }
+}
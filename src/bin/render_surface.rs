@@ -0,0 +1,273 @@
+// Renders a surface expression to a PNG file with the software backend, no browser needed
+//
+//   cargo run --bin render_surface -- "sin(x) * cos(z + t * 0.001)" -o plot.png --grid 150
+use std::fs::File;
+use std::io::BufWriter;
+use std::process::ExitCode;
+use web_sys::WebGlRenderingContext as GL;
+use rust_3d_demo::app_state::AppState;
+use rust_3d_demo::backend::software::SoftwareBackend;
+use rust_3d_demo::backend::RenderBackend;
+use rust_3d_demo::common_funcs as cf;
+use rust_3d_demo::constants as c;
+use rust_3d_demo::expression::Expression;
+use rust_3d_demo::gl_setup;
+use rust_3d_demo::plot_domain::{AxisRange, PlotDomain};
+use rust_3d_demo::programs::{BuiltinSurface, Graph3D, Light, SurfaceStyle};
+
+// Largest --width and --height, a common GL maximum viewport size.
+// The software backend keeps 20 bytes per pixel, so this is about 1.3 GB at most
+const MAX_IMAGE_SIZE: usize = 8192;
+
+const USAGE: &str = "\
+Usage: render_surface [OPTIONS] [EXPRESSION]
+
+Renders y = EXPRESSION(x, z, t) as a lit 3D surface and writes it to a PNG file.
+x and z range over the domain, [-2pi, 2pi] by default, t is the time in milliseconds.
+
+Options:
+      --builtin <NAME>  Draw ripple, waves or saddle with the GPU surface shader
+                        instead of EXPRESSION
+  -o, --output <FILE>   Output PNG file [default: surface.png]
+      --grid <N>        Grid cells along both x and z [default: 100]
+      --grid-x <N>      Grid cells along x
+      --grid-z <N>      Grid cells along z
+      --x-range <MIN,MAX>
+                        Range of x [default: -6.283,6.283]
+      --z-range <MIN,MAX>
+                        Range of z [default: -6.283,6.283]
+      --y-range <MIN,MAX|auto>
+                        Heights drawn from the bottom to the top of the plot,
+                        auto fits them to the surface, or to the bounds of the
+                        --builtin function [default: auto]
+      --normals <MODE>  forward, central or area [default: central]
+      --rot-x <RAD>     Rotation around the x-axis in radians [default: -0.5]
+      --rot-y <RAD>     Rotation around the y-axis in radians [default: 0.5]
+      --width <PX>      Image width, at most 8192 [default: 800]
+      --height <PX>     Image height, at most 8192 [default: 600]
+      --time <MS>       Value of t [default: 0]
+      --orthographic    Use an orthographic instead of a perspective camera
+      --colormap        Color the surface by height
+      --fog             Fade the far side of the surface into the background
+      --lighting <MODEL>
+                        lambert, phong or blinn-phong [default: lambert]
+      --light-direction <X,Y,Z>
+                        Towards the light, z towards the viewer [default: -0.85,0.8,0.75]
+      --specular <K>    Strength of the phong and blinn-phong highlights [default: 0.5]
+      --shininess <N>   Specular exponent [default: 32]
+  -h, --help            Print this message";
+
+struct Options {
+    expression: String,
+    builtin_surface: Option<BuiltinSurface>,
+    output: String,
+    grid_size: cf::GridSize,
+    domain: PlotDomain,
+    normal_mode: cf::NormalMode,
+    rotation_angle_x_axis: f32,
+    rotation_angle_y_axis: f32,
+    width: usize,
+    height: usize,
+    time: f32,
+    orthographic: bool,
+    style: SurfaceStyle,
+    light: Light,
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("Missing value for {}", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value '{}' for {}", value, flag))
+}
+
+fn parse_range(flag: &str, value: Option<String>) -> Result<AxisRange, String> {
+    let value = value.ok_or_else(|| format!("Missing value for {}", flag))?;
+    let invalid = || format!("Invalid value '{}' for {}, expected MIN,MAX", value, flag);
+    let (min, max) = value.split_once(',').ok_or_else(invalid)?;
+    let min = min.trim().parse().map_err(|_| invalid())?;
+    let max = max.trim().parse().map_err(|_| invalid())?;
+    AxisRange::new(min, max).map_err(|e| format!("{}: {}", flag, e))
+}
+
+fn parse_vector(flag: &str, value: Option<String>) -> Result<[f32; 3], String> {
+    let value = value.ok_or_else(|| format!("Missing value for {}", flag))?;
+    let invalid = || format!("Invalid value '{}' for {}, expected X,Y,Z", value, flag);
+    let components: Vec<f32> = value
+        .split(',')
+        .map(|component| component.trim().parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    components.try_into().map_err(|_| invalid())
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        expression: c::DEFAULT_SURFACE_EXPRESSION.to_string(),
+        builtin_surface: None,
+        output: String::from("surface.png"),
+        grid_size: cf::GridSize::square(c::DEFAULT_GRID_SIZE)?,
+        domain: PlotDomain::default(),
+        normal_mode: cf::NormalMode::default(),
+        rotation_angle_x_axis: -0.5,
+        rotation_angle_y_axis: 0.5,
+        width: 800,
+        height: 600,
+        time: 0.,
+        orthographic: false,
+        style: SurfaceStyle::default(),
+        light: Light::default(),
+    };
+    let mut expression = None;
+    let (mut grid_x, mut grid_z) = (options.grid_size.x(), options.grid_size.z());
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--builtin" => options.builtin_surface = Some(parse_value(&arg, args.next())?),
+            "-o" | "--output" => options.output = parse_value(&arg, args.next())?,
+            "--grid" => {
+                grid_x = parse_value(&arg, args.next())?;
+                grid_z = grid_x;
+            }
+            "--grid-x" => grid_x = parse_value(&arg, args.next())?,
+            "--grid-z" => grid_z = parse_value(&arg, args.next())?,
+            "--x-range" => options.domain.x = parse_range(&arg, args.next())?,
+            "--z-range" => options.domain.z = parse_range(&arg, args.next())?,
+            "--y-range" => {
+                let value = args.next();
+                options.domain.y = match value.as_deref() {
+                    Some("auto") => None,
+                    _ => Some(parse_range(&arg, value)?),
+                };
+            }
+            "--normals" => options.normal_mode = parse_value(&arg, args.next())?,
+            "--rot-x" => options.rotation_angle_x_axis = parse_value(&arg, args.next())?,
+            "--rot-y" => options.rotation_angle_y_axis = parse_value(&arg, args.next())?,
+            "--width" => options.width = parse_value(&arg, args.next())?,
+            "--height" => options.height = parse_value(&arg, args.next())?,
+            "--time" => options.time = parse_value(&arg, args.next())?,
+            "--orthographic" => options.orthographic = true,
+            "--colormap" => options.style.colormap = true,
+            // gl_setup::configure_context clears to black
+            "--fog" => options.style.fog = Some([0., 0., 0.]),
+            "--lighting" => options.style.lighting = parse_value(&arg, args.next())?,
+            "--light-direction" => options.light.direction = parse_vector(&arg, args.next())?,
+            "--specular" => options.light.specular = parse_value(&arg, args.next())?,
+            "--shininess" => options.light.shininess = parse_value(&arg, args.next())?,
+            flag if flag.starts_with("--") => return Err(format!("Unknown option {}", flag)),
+            _ if expression.is_none() => expression = Some(arg),
+            _ => return Err(format!("Unexpected argument '{}'", arg)),
+        }
+    }
+    match (expression, options.builtin_surface) {
+        (Some(_), Some(_)) => return Err(String::from("Give either an EXPRESSION or --builtin, not both")),
+        (Some(expression), None) => options.expression = expression,
+        (None, Some(builtin_surface)) => options.expression = builtin_surface.expression().to_string(),
+        (None, None) => {}
+    }
+
+    options.grid_size = cf::GridSize::new(grid_x, grid_z)?;
+    if options.width == 0 || options.height == 0 {
+        return Err(String::from("--width and --height must be positive"));
+    }
+    if options.width > MAX_IMAGE_SIZE || options.height > MAX_IMAGE_SIZE {
+        return Err(format!("--width and --height must be at most {}", MAX_IMAGE_SIZE));
+    }
+    Ok(Some(options))
+}
+
+fn render(options: &Options) -> Result<Vec<u8>, String> {
+    let surface = Expression::parse(&options.expression)
+        .map_err(|e| format!("Invalid surface expression: {}", e))?;
+
+    let gl = SoftwareBackend::new(options.width, options.height);
+    gl_setup::configure_context(&gl);
+    let mut graph = Graph3D::with_grid_size(&gl, options.grid_size).map_err(|e| e.to_string())?;
+    graph.set_normal_mode(options.normal_mode);
+    graph.set_style(&gl, options.style).map_err(|e| e.to_string())?;
+    graph.set_light(options.light).map_err(|e| e.to_string())?;
+
+    let mut state = AppState::new();
+    state.update_dynamic_data(options.time, options.height as f32, options.width as f32);
+
+    gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
+    let rotation = cf::rotation_from_euler_angles(options.rotation_angle_x_axis, options.rotation_angle_y_axis);
+    let orthographic_blend = if options.orthographic { 1. } else { 0. };
+    let builtin_y_range = options.builtin_surface.and_then(|builtin_surface| builtin_surface.y_range(&options.domain));
+    if let (Some(builtin_surface), Some(y_range)) = (options.builtin_surface, builtin_y_range) {
+        graph.render_builtin(
+            &gl,
+            state.control_bottom + c::GRAPH_MARGIN,
+            state.control_top - c::GRAPH_MARGIN,
+            state.control_left + c::GRAPH_MARGIN,
+            state.control_right - c::GRAPH_MARGIN,
+            state.canvas_height,
+            state.canvas_width,
+            &rotation,
+            state.zoom,
+            state.pan_x,
+            state.pan_y,
+            orthographic_blend,
+            builtin_surface,
+            state.time,
+            &options.domain,
+            y_range,
+        );
+        return Ok(gl.read_pixels());
+    }
+    let y_vals = cf::get_updated_y_values(state.time, options.grid_size, &options.domain, &surface);
+    graph.set_y_values(&gl, &y_vals).map_err(|e| e.to_string())?;
+    graph.render(
+        &gl,
+        state.control_bottom + c::GRAPH_MARGIN,
+        state.control_top - c::GRAPH_MARGIN,
+        state.control_left + c::GRAPH_MARGIN,
+        state.control_right - c::GRAPH_MARGIN,
+        state.canvas_height,
+        state.canvas_width,
+        &rotation,
+        state.zoom,
+        state.pan_x,
+        state.pan_y,
+        orthographic_blend,
+    ).map_err(|e| e.to_string())?;
+    Ok(gl.read_pixels())
+}
+
+fn write_png(path: &str, width: usize, height: usize, pixels: &[u8]) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Unable to create {}: {}", path, e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(pixels))
+        .map_err(|e| format!("Unable to write {}: {}", path, e))
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = render(&options)
+        .and_then(|pixels| write_png(&options.output, options.width, options.height, &pixels));
+    match result {
+        Ok(()) => {
+            println!("Wrote {}", options.output);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,30 +1,43 @@
-use nalgebra::{Perspective3, Matrix4};
+use nalgebra::{Orthographic3, Perspective3, UnitQuaternion, Vector3};
+use crate::error::{Error, ShaderStage};
+use crate::expression::Expression;
+use crate::plot_domain::PlotDomain;
+use crate::shaders::preprocessor::preprocess;
 use web_sys::WebGlRenderingContext as GL;
 use web_sys::*;
 
 pub fn link_program(
     gl: &WebGlRenderingContext,
     vert_source: &str,
-    frag_source: &str
-) -> Result<WebGlProgram, String> {
+    frag_source: &str,
+    defines: &[&str],
+    attributes: &[&str]
+) -> Result<WebGlProgram, Error> {
+    // Compile errors then quote the expanded source, which is what their line numbers refer to
+    let vert_source = preprocess(vert_source, defines)?;
+    let frag_source = preprocess(frag_source, defines)?;
     let program = gl
         .create_program()
-        .ok_or_else(|| String::from("Unable to create program object"))?;
+        .ok_or_else(|| Error::Link(String::from("Unable to create program object")))?;
 
     let vert_shader = compile_shader(
-        &gl,
-        GL::VERTEX_SHADER,
-        vert_source
-    ).unwrap();
+        gl,
+        ShaderStage::Vertex,
+        &vert_source
+    )?;
 
     let frag_shader = compile_shader(
-        &gl,
-        GL::FRAGMENT_SHADER,
-        frag_source
-    ).unwrap();
+        gl,
+        ShaderStage::Fragment,
+        &frag_source
+    )?;
 
     gl.attach_shader(&program, &vert_shader);
     gl.attach_shader(&program, &frag_shader);
+    // Otherwise the driver picks the locations
+    for (location, name) in attributes.iter().enumerate() {
+        gl.bind_attrib_location(&program, location as u32, name);
+    }
     gl.link_program(&program);
 
     if gl.get_program_parameter(&program, GL::LINK_STATUS)
@@ -33,20 +46,29 @@ pub fn link_program(
     {
         Ok(program)
     } else {
-        Err(gl.get_program_info_log(&program)
-            .unwrap_or_else(|| String::from("Unknown error creating program object")))
+        Err(Error::Link(gl.get_program_info_log(&program)
+            .unwrap_or_else(|| String::from("Unknown error creating program object"))))
     }
 }
 
 
 fn compile_shader(
     gl: &WebGlRenderingContext,
-    shader_type: u32,
+    stage: ShaderStage,
     source: &str
-) -> Result<WebGlShader, String> {
+) -> Result<WebGlShader, Error> {
+    let shader_type = match stage {
+        ShaderStage::Vertex => GL::VERTEX_SHADER,
+        ShaderStage::Fragment => GL::FRAGMENT_SHADER,
+    };
     let shader = gl
         .create_shader(shader_type)
-        .ok_or_else(|| String::from("Unable to create shader object"))?;
+        .ok_or_else(|| Error::ShaderCompile {
+            stage,
+            log: String::from("Unable to create shader object"),
+            diagnostics: Vec::new(),
+            source: source.to_string(),
+        })?;
     
     gl.shader_source(&shader, source);
     gl.compile_shader(&shader);
@@ -58,9 +80,10 @@ fn compile_shader(
     {
         Ok(shader)
     } else {
-        Err(gl
+        let log = gl
             .get_shader_info_log(&shader)
-            .unwrap_or_else(|| String::from("Unknown error creating shader")))
+            .unwrap_or_else(|| String::from("Unknown error creating shader"));
+        Err(Error::shader_compile(stage, log, source.to_string()))
     }
 }
 
@@ -110,50 +133,136 @@ pub fn mult_matrix_4(a: [f32; 16], b: [f32; 16]) -> [f32; 16]
     return_var
 }
 
-pub fn get_position_grid_n_by_n(n: usize) -> (Vec<f32>, Vec<u16>)
+// Number of grid cells along x and along z, only built through new so the
+// vertex and index counts of the mesh always fit in memory and in u32
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridSize {
+    x: usize,
+    z: usize,
+}
+
+impl GridSize {
+    pub fn new(x: usize, z: usize) -> Result<Self, String> {
+        use crate::constants as c;
+        if x == 0 || z == 0 {
+            return Err(format!("Grid size must be at least 1 x 1, got {} x {}", x, z));
+        }
+        let cell_count = x.checked_mul(z);
+        if cell_count.is_none_or(|count| count > c::MAX_GRID_CELLS) {
+            return Err(format!("Grid size {} x {} has more than {} cells", x, z, c::MAX_GRID_CELLS));
+        }
+        Ok(GridSize { x, z })
+    }
+
+    pub fn square(n: usize) -> Result<Self, String> {
+        Self::new(n, n)
+    }
+
+    pub fn x(&self) -> usize {
+        self.x
+    }
+
+    pub fn z(&self) -> usize {
+        self.z
+    }
+
+    pub fn cell_count(&self) -> usize {
+        self.x * self.z
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        (self.x + 1) * (self.z + 1)
+    }
+}
+
+// Element indices in the smallest type that can address every vertex
+pub enum MeshIndices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl MeshIndices {
+    pub fn len(&self) -> usize {
+        match self {
+            MeshIndices::U16(indices) => indices.len(),
+            MeshIndices::U32(indices) => indices.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // GL::UNSIGNED_SHORT or GL::UNSIGNED_INT, for draw_elements
+    pub fn gl_type(&self) -> u32 {
+        match self {
+            MeshIndices::U16(_) => GL::UNSIGNED_SHORT,
+            MeshIndices::U32(_) => GL::UNSIGNED_INT,
+        }
+    }
+}
+
+pub fn get_grid_positions(grid_size: GridSize) -> Vec<f32>
 {
-    let n_plus_one = n + 1;
+    let points_per_row = grid_size.x() + 1;
     // X, Y, Z
     // Make it more efficient by storing just x and z and computing y on the fly
-    let mut positions: Vec<f32> = vec![0.; n_plus_one * n_plus_one * 3];
-    let mut indices: Vec<u16> = vec![0; n * n * 6]; // 3 vertices per triangle, 2 triangles per square = 6 indices per square
+    let float_count = grid_size.vertex_count().checked_mul(3).expect("GridSize::new caps the cell count");
+    let mut positions: Vec<f32> = vec![0.; float_count];
  
     // WebGL display goes from -1 to 1, so the "width" is equal to 2
     let graph_layout_width: f32 = 2.;
 
-    let square_size: f32 = graph_layout_width / (n as f32);
+    let square_size_x: f32 = graph_layout_width / (grid_size.x() as f32);
+    let square_size_z: f32 = graph_layout_width / (grid_size.z() as f32);
 
-    for i in 0..n_plus_one {
-        for j in 0..n_plus_one {
+    for i in 0..grid_size.z() + 1 {
+        for j in 0..points_per_row {
             // Convert from 2D to 1D array, * 3 to account for X, Y, Z
             // i,j coordinates are grid-cell positions. We store x,y,z coordinates for each vertex in the grid
-            let start_pos = (i * n_plus_one + j) * 3; 
-            positions[start_pos] = -1. + (j as f32) * square_size;
+            let start_pos = (i * points_per_row + j) * 3; 
+            positions[start_pos] = -1. + (j as f32) * square_size_x;
             positions[start_pos + 1] = 0.;
-            positions[start_pos + 2] = -1. + (i as f32) * square_size; 
-
-            // Don't add indices for the last row or column
-            if i < n && j < n
-            {
-                let top_left = (i * n_plus_one + j) as u16;
-                let bottom_left = top_left + n_plus_one as u16;
-
-                let top_right = top_left + 1; // they are stored in a row-major order
-                let bottom_right = bottom_left + 1;
-
-                // Define counter-clockwise winding order vertices
-                // for the two triangles that make up a square
-                indices[(i * n + j) * 6] = top_left;
-                indices[(i * n + j) * 6 + 1] = bottom_left;
-                indices[(i * n + j) * 6 + 2] = top_right;
-
-                indices[(i * n + j) * 6 + 3] = top_right;
-                indices[(i * n + j) * 6 + 4] = bottom_left;
-                indices[(i * n + j) * 6 + 5] = bottom_right;
-            }
+            positions[start_pos + 2] = -1. + (i as f32) * square_size_z; 
         }
     }
-    (positions, indices)
+    positions
+}
+
+// u16 indices when the grid has at most 65536 vertices, u32 otherwise
+pub fn get_grid_indices(grid_size: GridSize) -> MeshIndices
+{
+    let points_per_row = grid_size.x() + 1;
+    // 3 vertices per triangle, 2 triangles per square = 6 indices per square
+    let index_count = grid_size.cell_count().checked_mul(6).expect("GridSize::new caps the cell count");
+    let mut indices: Vec<u32> = vec![0; index_count];
+
+    for i in 0..grid_size.z() {
+        for j in 0..grid_size.x() {
+            let top_left = (i * points_per_row + j) as u32;
+            let bottom_left = top_left + points_per_row as u32;
+
+            let top_right = top_left + 1; // they are stored in a row-major order
+            let bottom_right = bottom_left + 1;
+
+            // Define counter-clockwise winding order vertices
+            // for the two triangles that make up a square
+            let square = (i * grid_size.x() + j) * 6;
+            indices[square] = top_left;
+            indices[square + 1] = bottom_left;
+            indices[square + 2] = top_right;
+
+            indices[square + 3] = top_right;
+            indices[square + 4] = bottom_left;
+            indices[square + 5] = bottom_right;
+        }
+    }
+
+    if grid_size.vertex_count() <= u16::MAX as usize + 1 {
+        MeshIndices::U16(indices.into_iter().map(|index| index as u16).collect())
+    } else {
+        MeshIndices::U32(indices)
+    }
 }
 
 
@@ -161,13 +270,18 @@ pub struct Matrices3D {
     pub normals_rotation: [f32; 16],
     pub projection: [f32; 16],
 }
+#[allow(clippy::too_many_arguments)]
 pub fn get_3d_matrices(bottom: f32,
     top:f32,
     left: f32,
     right: f32,
     canvas_height: f32,
     canvas_width: f32,
-    rotation_angle_x_axis: f32, rotation_angle_y_axis: f32)
+    rotation: &UnitQuaternion<f32>,
+    zoom: f32,
+    pan_x: f32,
+    pan_y: f32,
+    orthographic_blend: f32)
  -> Matrices3D
  {
     use crate::constants as c;
@@ -176,37 +290,30 @@ pub fn get_3d_matrices(bottom: f32,
         projection: [0.; 16],
     };
 
-    let rotate_x_mat : [f32; 16] = [
-        1.0, 0.0, 0.0, 0.0,
-        0.0, rotation_angle_x_axis.cos(), -rotation_angle_x_axis.sin(), 0.0,
-        0.0, rotation_angle_x_axis.sin(), rotation_angle_x_axis.cos(), 0.0,
-        0.0, 0.0, 0.0, 1.0,
-    ];
-    let rotate_y_mat: [f32; 16] = [
-        rotation_angle_y_axis.cos(), 0.0, rotation_angle_y_axis.sin(), 0.0,
-        0.0, 1.0, 0.0, 0.0,
-        -rotation_angle_y_axis.sin(), 0.0, rotation_angle_y_axis.cos(), 0.0,
-        0.0, 0.0, 0.0, 1.0,
-    ];
-    let rotation_matrix = mult_matrix_4(rotate_x_mat, rotate_y_mat);
+    // The arrays here are multiplied as row-major and uploaded as column-major, so the
+    // column-major data of the rotation is what the shader applies to column vectors
+    let mut rotation_matrix: [f32; 16] = [0.; 16];
+    rotation_matrix.copy_from_slice(rotation.to_homogeneous().as_slice());
 
     let aspect_ratio = canvas_width / canvas_height;
     let x_range = right - left;
     let y_range = top - bottom;
     let scale_x = x_range / canvas_width;
     let scale_y = y_range / canvas_height;
-    let scale = scale_y;
+    let scale = scale_y * zoom;
 
+    // Pan is in units of half the canvas height, which is one unit at the Z_PLANE distance
     let translate_mat = translation_matrix(
-        -1. + scale_x + 2. * left / canvas_width,
-        -1. + scale_y + 2. * bottom / canvas_height,
+        -1. + scale_x + 2. * left / canvas_width + pan_x,
+        -1. + scale_y + 2. * bottom / canvas_height + pan_y,
         c::Z_PLANE,
     );
 
+    // Depth is kept so the perspective projection shows foreshortening
     let scale_mat = scaling_matrix(
         scale,
         scale,
-        0.,
+        scale,
     );
     let rotation_scale = mult_matrix_4(rotation_matrix, scale_mat);
     let combined_transform = mult_matrix_4(rotation_scale, translate_mat);
@@ -216,116 +323,225 @@ pub fn get_3d_matrices(bottom: f32,
         c::Z_NEAR,
         c::Z_FAR,
     );
+    // The view volume at the Z_PLANE distance spans [-aspect, aspect] x [-1, 1], so this
+    // orthographic box keeps the graph the same size when switching between the two
+    let orthographic_mat_tmp: Orthographic3<f32> = Orthographic3::new(
+        -aspect_ratio,
+        aspect_ratio,
+        -1.,
+        1.,
+        c::Z_NEAR,
+        c::Z_FAR,
+    );
+    // Blending the matrices (rather than switching) gives a smooth transition,
+    // and sizes at the Z_PLANE distance stay the same throughout
     let mut perspective: [f32; 16] = [0.; 16];
-    perspective.copy_from_slice(perspective_mat_tmp.as_matrix().as_slice());
+    for (i, value) in perspective.iter_mut().enumerate() {
+        let p = perspective_mat_tmp.as_matrix().as_slice()[i];
+        let o = orthographic_mat_tmp.as_matrix().as_slice()[i];
+        *value = p + (o - p) * orthographic_blend;
+    }
     
     return_var.projection =  mult_matrix_4(combined_transform, perspective);
 
-// Assign rotation_matrix to normals_rotation
-    let normal_matrix = Matrix4::new(
-        rotation_matrix[0], rotation_matrix[1], rotation_matrix[2], rotation_matrix[3],
-        rotation_matrix[4], rotation_matrix[5], rotation_matrix[6], rotation_matrix[7],
-        rotation_matrix[8], rotation_matrix[9], rotation_matrix[10], rotation_matrix[11],
-        rotation_matrix[12], rotation_matrix[13], rotation_matrix[14], rotation_matrix[15],
-    );
-    match normal_matrix.try_inverse() {
-        Some(inv) => {
-            return_var.normals_rotation.copy_from_slice(inv.as_slice());
-        }, 
-        None => {}
-    };
+    // The inverse transpose of a rotation is the rotation itself
+    return_var.normals_rotation = rotation_matrix;
     return_var
 }
 
+// Orientation that the old pair of Euler angles produced (rotate around x, then around y)
+pub fn rotation_from_euler_angles(rotation_angle_x_axis: f32, rotation_angle_y_axis: f32) -> UnitQuaternion<f32>
+{
+    UnitQuaternion::from_axis_angle(&Vector3::y_axis(), -rotation_angle_y_axis)
+        * UnitQuaternion::from_axis_angle(&Vector3::x_axis(), -rotation_angle_x_axis)
+}
 
-pub fn get_updated_y_values(curr_time: f32) -> Vec<f32>
+// Projects a point in the control area onto the virtual arcball sphere, a unit vector
+// Points outside the sphere land on its rim, so dragging around the outside spins the graph in the view plane
+pub fn arcball_vector(x: f32, y: f32, center_x: f32, center_y: f32, radius: f32) -> Vector3<f32>
 {
-    use crate::constants as c;
-    let points_per_row = c::GRID_SIZE + 1;
-    let mut y_vals: Vec<f32> = vec![0.; points_per_row * points_per_row];
-    
-    let half_width: f32 = points_per_row as f32 / 2.0;
-    let frequency_scale = 2. * std::f32::consts::PI;
-    let y_scale = 0.15;
+    let px = (x - center_x) / radius;
+    let py = (y - center_y) / radius;
+    let distance_squared = px * px + py * py;
+    if distance_squared <= 1. {
+        Vector3::new(px, py, (1. - distance_squared).sqrt())
+    } else {
+        Vector3::new(px, py, 0.) / distance_squared.sqrt()
+    }
+}
 
-    let sin_offset = curr_time * 0.001;
- 
-    for z in 0..points_per_row
+
+// x and z passed to the expression span the ranges of `domain` across the grid, t is the time in milliseconds
+// Samples the surface over the domain and returns the heights in display coordinates
+pub fn get_updated_y_values(curr_time: f32, grid_size: GridSize, domain: &PlotDomain, surface: &Expression) -> Vec<f32>
+{
+    let mut y_vals = Vec::new();
+    update_y_values(curr_time, grid_size, domain, surface, &mut y_vals);
+    y_vals
+}
+
+// Like get_updated_y_values, reusing the allocation of `y_vals`
+pub fn update_y_values(curr_time: f32, grid_size: GridSize, domain: &PlotDomain, surface: &Expression, y_vals: &mut Vec<f32>)
+{
+    let points_per_row = grid_size.x() + 1;
+    y_vals.resize(grid_size.vertex_count(), 0.);
+
+    for z in 0..grid_size.z() + 1
     {
+        let data_z = domain.z.lerp(z as f32 / grid_size.z() as f32);
         for x in 0..points_per_row
         {
             let index = z * points_per_row + x;
+            let data_x = domain.x.lerp(x as f32 / grid_size.x() as f32);
+            y_vals[index] = surface.eval(data_x, data_z, curr_time);
+        }
+    }
+    domain.normalize_y(y_vals);
+}
+
+// How get_grid_normals computes the normal at each vertex
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NormalMode {
+    // Normal of the triangle to the right of and below the vertex, faceted.
+    // The last row and column take their neighbour above or to the left instead
+    ForwardTriangle,
+    // Slopes from the neighbours on both sides, one-sided at the borders
+    #[default]
+    CentralDifference,
+    // Sum of the adjacent triangle normals, weighted by the triangle areas
+    AreaWeighted,
+}
 
-            let scaled_x =  frequency_scale * (x as f32 - half_width)/half_width;
-            let scaled_z =  frequency_scale * (z as f32 - half_width)/half_width;
-            y_vals[index] = y_scale * ((scaled_x*scaled_x + scaled_z*scaled_z).sqrt() + sin_offset).sin();
+impl std::str::FromStr for NormalMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "forward" => Ok(NormalMode::ForwardTriangle),
+            "central" => Ok(NormalMode::CentralDifference),
+            "area" => Ok(NormalMode::AreaWeighted),
+            _ => Err(format!("Unknown normal mode '{}', expected one of forward, central, area", s)),
         }
     }
-    y_vals
 }
 
-pub fn get_grid_normals(grid_size: usize, y_vals: &[f32]) -> Vec<f32>
+pub fn get_grid_normals(grid_size: GridSize, y_vals: &[f32], mode: NormalMode) -> Vec<f32>
+{
+    let mut normals = Vec::new();
+    update_grid_normals(grid_size, y_vals, mode, &mut normals);
+    normals
+}
+
+// Like get_grid_normals, reusing the allocation of `normals`
+pub fn update_grid_normals(grid_size: GridSize, y_vals: &[f32], mode: NormalMode, normals: &mut Vec<f32>)
+{
+    normals.resize(grid_size.vertex_count() * 3, 0.);
+    match mode {
+        NormalMode::ForwardTriangle => write_forward_triangle_normals(grid_size, y_vals, normals),
+        NormalMode::CentralDifference => write_central_difference_normals(grid_size, y_vals, normals),
+        NormalMode::AreaWeighted => write_area_weighted_normals(grid_size, y_vals, normals),
+    }
+}
+
+fn write_forward_triangle_normals(grid_size: GridSize, y_vals: &[f32], normals: &mut [f32])
 {
-    let points_per_row = grid_size + 1;
+    let points_per_row = grid_size.x() + 1;
     let graph_layout_width: f32 = 2.;
-    let square_size = graph_layout_width / (grid_size as f32);
-    let mut normals: Vec<f32> = vec![0.; points_per_row * points_per_row * 3];
+    let square_size_x = graph_layout_width / (grid_size.x() as f32);
+    let square_size_z = graph_layout_width / (grid_size.z() as f32);
 
-    for i in 0..points_per_row
+    for i in 0..grid_size.z() + 1
     {
+        // The last row has no row below it, so it uses the one above
+        let i_next = if i == grid_size.z() { i - 1 } else { i + 1 };
         for j in 0..points_per_row
         {
+            let j_next = if j == grid_size.x() { j - 1 } else { j + 1 };
             let y_index_a = i * points_per_row + j;
+
+            // Plane through the vertex and its neighbours along x and along z
+            let slope_x = (y_vals[i * points_per_row + j_next] - y_vals[y_index_a])
+                / ((j_next as f32 - j as f32) * square_size_x);
+            let slope_z = (y_vals[i_next * points_per_row + j] - y_vals[y_index_a])
+                / ((i_next as f32 - i as f32) * square_size_z);
+
+            let normal_vec = Vector3::new(-slope_x, 1., -slope_z).normalize();
             let return_var_start_pos = y_index_a * 3;
+            normals[return_var_start_pos] = normal_vec.x;
+            normals[return_var_start_pos + 1] = normal_vec.y;
+            normals[return_var_start_pos + 2] = normal_vec.z;
+        }
+    }
+}
 
-            if i == grid_size || j == grid_size
-            {
-                // "up" is the default at the edge ([0, 1, 0])
-                normals[return_var_start_pos + 1] = 1.0;
-            }else{
-                // Get normal vector of triangle defined by three points
-                let y_index_b = y_index_a + points_per_row;  // down
-                let y_index_c = y_index_a + 1;               // right
-
-                let x_val_1 = j as f32 * square_size;
-                let x_val_2 = x_val_1 + square_size;
-
-                let z_val_1 = i as f32 * square_size;
-                let z_val_2 = z_val_1 + square_size;
-                
-                let normal_vec = get_normal_vec(
-                    x_val_1, y_vals[y_index_a], z_val_1,
-                    x_val_1, y_vals[y_index_b], z_val_2,
-                    x_val_2, y_vals[y_index_c], z_val_1,
-                ); 
-                normals[return_var_start_pos] = normal_vec.0;
-                normals[return_var_start_pos + 1] = normal_vec.1;
-                normals[return_var_start_pos + 2] = normal_vec.2;
-            }
+fn write_central_difference_normals(grid_size: GridSize, y_vals: &[f32], normals: &mut [f32])
+{
+    let points_per_row = grid_size.x() + 1;
+    let graph_layout_width: f32 = 2.;
+    let square_size_x = graph_layout_width / (grid_size.x() as f32);
+    let square_size_z = graph_layout_width / (grid_size.z() as f32);
+
+    for i in 0..grid_size.z() + 1
+    {
+        // Neighbouring rows and columns, the vertex itself at the borders
+        let (i_before, i_after) = (i.saturating_sub(1), (i + 1).min(grid_size.z()));
+        for j in 0..points_per_row
+        {
+            let (j_before, j_after) = (j.saturating_sub(1), (j + 1).min(grid_size.x()));
+
+            let slope_x = (y_vals[i * points_per_row + j_after] - y_vals[i * points_per_row + j_before])
+                / ((j_after - j_before) as f32 * square_size_x);
+            let slope_z = (y_vals[i_after * points_per_row + j] - y_vals[i_before * points_per_row + j])
+                / ((i_after - i_before) as f32 * square_size_z);
+
+            // Normal of the tangent plane y = slope_x * x + slope_z * z
+            let normal_vec = Vector3::new(-slope_x, 1., -slope_z).normalize();
+            let return_var_start_pos = (i * points_per_row + j) * 3;
+            normals[return_var_start_pos] = normal_vec.x;
+            normals[return_var_start_pos + 1] = normal_vec.y;
+            normals[return_var_start_pos + 2] = normal_vec.z;
         }
     }
-    normals
 }
-pub fn get_normal_vec(point_a_x: f32, point_a_y: f32, point_a_z: f32,
-    point_b_x: f32, point_b_y: f32, point_b_z: f32,
-    point_c_x: f32, point_c_y: f32, point_c_z: f32) -> (f32, f32, f32)
+
+fn write_area_weighted_normals(grid_size: GridSize, y_vals: &[f32], normals: &mut [f32])
 {
-    // Compute normal vector of triangle defined by three points
-    // https://www.khronos.org/opengl/wiki/Calculating_a_Surface_Normal
-    let u_x = point_b_x - point_a_x;
-    let u_y = point_b_y - point_a_y;
-    let u_z = point_b_z - point_a_z;
-    
-    let v_x = point_c_x - point_a_x;
-    let v_y = point_c_y - point_a_y;
-    let v_z = point_c_z - point_a_z;
-
-    // Cross product
-    let normal_x = u_y * v_z - u_z * v_y;
-    let normal_y = u_z * v_x - u_x * v_z;
-    let normal_z = u_x * v_y - u_y * v_x;
-
-    // Normalize
-    let normal_length = (normal_x * normal_x + normal_y * normal_y + normal_z * normal_z).sqrt();
-    (normal_x / normal_length, normal_y / normal_length, normal_z / normal_length)
+    let points_per_row = grid_size.x() + 1;
+    let graph_layout_width: f32 = 2.;
+    let square_size_x = graph_layout_width / (grid_size.x() as f32);
+    let square_size_z = graph_layout_width / (grid_size.z() as f32);
+    // Sums of the adjacent triangle normals
+    normals.fill(0.);
+
+    let vertex = |index: usize| Vector3::new(
+        (index % points_per_row) as f32 * square_size_x,
+        y_vals[index],
+        (index / points_per_row) as f32 * square_size_z,
+    );
+
+    for i in 0..grid_size.z()
+    {
+        for j in 0..grid_size.x()
+        {
+            let top_left = i * points_per_row + j;
+            let bottom_left = top_left + points_per_row;
+            let top_right = top_left + 1;
+            let bottom_right = bottom_left + 1;
+
+            // Same triangles and winding as get_grid_indices. The length of the cross
+            // product is twice the triangle area, which does the weighting
+            for [a, b, c] in [[top_left, bottom_left, top_right], [top_right, bottom_left, bottom_right]] {
+                let normal = (vertex(b) - vertex(a)).cross(&(vertex(c) - vertex(a)));
+                for index in [a, b, c] {
+                    normals[index * 3] += normal.x;
+                    normals[index * 3 + 1] += normal.y;
+                    normals[index * 3 + 2] += normal.z;
+                }
+            }
+        }
+    }
+    for normal in normals.chunks_exact_mut(3) {
+        let normal_vec = Vector3::new(normal[0], normal[1], normal[2]).normalize();
+        normal.copy_from_slice(normal_vec.as_slice());
+    }
 }
@@ -1,8 +1,34 @@
 pub const FIELD_OF_VIEW: f32 = 45. * std::f32::consts::PI / 180.; // in radians
-pub const GRID_SIZE: usize = 100;
+// Grid cells per side until Client::set_grid_size is called
+pub const DEFAULT_GRID_SIZE: usize = 100;
+// Largest number of grid cells, e.g. 2000 x 2000. The mesh then takes about 200 MB
+// of buffers, well within the 32-bit address space of wasm
+pub const MAX_GRID_CELLS: usize = 4_000_000;
+// Gap in pixels between the edge of the control area and the 3D graph
+pub const GRAPH_MARGIN: f32 = 20.;
+// Ripple that was originally hard-coded in get_updated_y_values
+pub const DEFAULT_SURFACE_EXPRESSION: &str = "0.15 * sin(sqrt(x^2 + z^2) + t * 0.001)";
+
+// Limits for the wheel zoom (scale factor) and drag pan (in units of half the canvas height)
+pub const MIN_ZOOM: f32 = 0.2;
+pub const MAX_ZOOM: f32 = 8.0;
+pub const ZOOM_SPEED: f32 = 0.001; // per pixel of wheel delta
+pub const MAX_PAN: f32 = 2.0;
+// Fraction of the control area that fit-to-data fills
+pub const FIT_TO_DATA_FILL: f32 = 0.9;
+
+// Decay rate (per second) of the spin that continues after a drag is released
+pub const DEFAULT_ROTATION_DAMPING: f32 = 2.0;
+// Below this (radians per millisecond) the spin stops
+pub const MIN_ANGULAR_VELOCITY: f32 = 1e-5;
+// A drag held still this long (in milliseconds) before it is released does not spin
+pub const INERTIA_HOLD_MS: f32 = 50.0;
+
+// Duration of the switch between perspective and orthographic projection
+pub const PROJECTION_TRANSITION_MS: f32 = 300.0;
 
 pub const Z_NEAR: f32 = 0.1;
 pub const Z_FAR: f32 = 100.0;
 // pub const Z_PLANE: f32 = -1.0 / (FIELD_OF_VIEW/2.0).tan();
-pub const Z_PLANE: f32 = -2.414213562373095;
+pub const Z_PLANE: f32 = -2.414_213_6;
 // pub const Z_PLANE: f32 = -2.414213562373095 - 1.7673;
@@ -0,0 +1,95 @@
+// Errors from setting up and drawing with a RenderBackend
+// Client turns them into JS Error objects
+use std::fmt;
+use wasm_bindgen::{JsCast, JsValue};
+use crate::shader_diagnostics::{self as diagnostics, Diagnostic};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+}
+
+impl fmt::Display for ShaderStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShaderStage::Vertex => write!(f, "vertex"),
+            ShaderStage::Fragment => write!(f, "fragment"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    // No canvas, no WebGL, or the context was lost
+    Context(String),
+    // A Client option with the wrong type or value
+    InvalidOption(String),
+    // `log` is the driver's info log for `source`, `diagnostics` what could be parsed from it
+    ShaderCompile {
+        stage: ShaderStage,
+        log: String,
+        diagnostics: Vec<Diagnostic>,
+        source: String,
+    },
+    // Unknown or malformed #include, or an invalid #define, see shaders::preprocessor
+    Preprocess(String),
+    Link(String),
+    MissingAttribute(String),
+    BufferAllocation,
+    // Grid or height data a program cannot draw
+    Mesh(String),
+    // Exception thrown by a browser API
+    Js(String),
+}
+
+impl Error {
+    pub fn shader_compile(stage: ShaderStage, log: String, source: String) -> Self {
+        Error::ShaderCompile {
+            stage,
+            diagnostics: diagnostics::parse_info_log(&log),
+            log,
+            source,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Context(message) => write!(f, "{}", message),
+            Error::InvalidOption(message) => write!(f, "{}", message),
+            Error::ShaderCompile { stage, log, diagnostics, source } => {
+                if diagnostics.is_empty() {
+                    return write!(f, "The {} shader failed to compile: {}", stage, log.trim());
+                }
+                write!(f, "The {} shader failed to compile:\n{}", stage, diagnostics::annotate(source, diagnostics))
+            }
+            Error::Preprocess(message) => write!(f, "Unable to preprocess the shaders: {}", message),
+            Error::Link(log) => write!(f, "The shader program failed to link: {}", log.trim()),
+            Error::MissingAttribute(name) => write!(f, "The shader program has no attribute '{}'", name),
+            Error::BufferAllocation => write!(f, "Unable to create a GPU buffer"),
+            Error::Mesh(message) => write!(f, "{}", message),
+            Error::Js(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<JsValue> for Error {
+    fn from(value: JsValue) -> Self {
+        let message = value
+            .dyn_ref::<js_sys::Error>()
+            .map(|error| String::from(error.message()))
+            .or_else(|| value.as_string())
+            .unwrap_or_else(|| format!("{:?}", value));
+        Error::Js(message)
+    }
+}
+
+impl From<Error> for JsValue {
+    fn from(error: Error) -> Self {
+        js_sys::Error::new(&error.to_string()).into()
+    }
+}
@@ -0,0 +1,415 @@
+// A tiny math-expression language used to describe the height field drawn by Graph3D
+// Supports the variables x, z and t, numeric literals, the constants pi and e,
+// the binary operators + - * / ^, unary minus, parentheses and a handful of functions
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Variable {
+    X,
+    Z,
+    T,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Exp,
+    Log,
+    Log10,
+    Abs,
+    Sqrt,
+    Floor,
+    Ceil,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        let func = match name {
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" => Function::Tan,
+            "asin" => Function::Asin,
+            "acos" => Function::Acos,
+            "atan" => Function::Atan,
+            "sinh" => Function::Sinh,
+            "cosh" => Function::Cosh,
+            "tanh" => Function::Tanh,
+            "exp" => Function::Exp,
+            "log" | "ln" => Function::Log,
+            "log10" => Function::Log10,
+            "abs" => Function::Abs,
+            "sqrt" => Function::Sqrt,
+            "floor" => Function::Floor,
+            "ceil" => Function::Ceil,
+            _ => return None,
+        };
+        Some(func)
+    }
+
+    fn apply(self, v: f32) -> f32 {
+        match self {
+            Function::Sin => v.sin(),
+            Function::Cos => v.cos(),
+            Function::Tan => v.tan(),
+            Function::Asin => v.asin(),
+            Function::Acos => v.acos(),
+            Function::Atan => v.atan(),
+            Function::Sinh => v.sinh(),
+            Function::Cosh => v.cosh(),
+            Function::Tanh => v.tanh(),
+            Function::Exp => v.exp(),
+            Function::Log => v.ln(),
+            Function::Log10 => v.log10(),
+            Function::Abs => v.abs(),
+            Function::Sqrt => v.sqrt(),
+            Function::Floor => v.floor(),
+            Function::Ceil => v.ceil(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Number(f32),
+    Var(Variable),
+    Neg(Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+    Call(Function, Box<Node>),
+}
+
+impl Node {
+    fn eval(&self, x: f32, z: f32, t: f32) -> f32 {
+        match self {
+            Node::Number(v) => *v,
+            Node::Var(Variable::X) => x,
+            Node::Var(Variable::Z) => z,
+            Node::Var(Variable::T) => t,
+            Node::Neg(inner) => -inner.eval(x, z, t),
+            Node::Binary(op, lhs, rhs) => {
+                let a = lhs.eval(x, z, t);
+                let b = rhs.eval(x, z, t);
+                match op {
+                    BinaryOp::Add => a + b,
+                    BinaryOp::Sub => a - b,
+                    BinaryOp::Mul => a * b,
+                    BinaryOp::Div => a / b,
+                    BinaryOp::Pow => a.powf(b),
+                }
+            }
+            Node::Call(func, arg) => func.apply(arg.eval(x, z, t)),
+        }
+    }
+
+    fn uses_time(&self) -> bool {
+        match self {
+            Node::Number(_) | Node::Var(Variable::X) | Node::Var(Variable::Z) => false,
+            Node::Var(Variable::T) => true,
+            Node::Neg(inner) | Node::Call(_, inner) => inner.uses_time(),
+            Node::Binary(_, lhs, rhs) => lhs.uses_time() || rhs.uses_time(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f32),
+    Ident(String),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Caret,
+    LeftParen,
+    RightParen,
+}
+
+// Splits the source into tokens, each tagged with its byte offset for error messages
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some(&(pos, ch)) = chars.peek() {
+        if ch.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        if ch.is_ascii_digit() || ch == '.' {
+            let bytes = source.as_bytes();
+            let mut end = pos;
+            while end < bytes.len() && (bytes[end].is_ascii_digit() || bytes[end] == b'.') {
+                end += 1;
+            }
+            // Optional exponent, e.g. 1.5e-3. A bare "e" is left alone so "2e" is not a number
+            if end < bytes.len() && (bytes[end] == b'e' || bytes[end] == b'E') {
+                let mut exp_end = end + 1;
+                if exp_end < bytes.len() && (bytes[exp_end] == b'+' || bytes[exp_end] == b'-') {
+                    exp_end += 1;
+                }
+                if exp_end < bytes.len() && bytes[exp_end].is_ascii_digit() {
+                    while exp_end < bytes.len() && bytes[exp_end].is_ascii_digit() {
+                        exp_end += 1;
+                    }
+                    end = exp_end;
+                }
+            }
+            while chars.peek().is_some_and(|&(i, _)| i < end) {
+                chars.next();
+            }
+            let literal = &source[pos..end];
+            let value = literal
+                .parse::<f32>()
+                .map_err(|_| format!("Invalid number '{}' at position {}", literal, pos))?;
+            tokens.push((pos, Token::Number(value)));
+            continue;
+        }
+
+        if ch.is_ascii_alphabetic() || ch == '_' {
+            let mut end = pos;
+            while let Some(&(i, c)) = chars.peek() {
+                if c.is_ascii_alphanumeric() || c == '_' {
+                    end = i + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push((pos, Token::Ident(source[pos..end].to_ascii_lowercase())));
+            continue;
+        }
+
+        let token = match ch {
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Star,
+            '/' => Token::Slash,
+            '^' => Token::Caret,
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            _ => return Err(format!("Unexpected character '{}' at position {}", ch, pos)),
+        };
+        tokens.push((pos, token));
+        chars.next();
+    }
+    Ok(tokens)
+}
+
+// Deepest the parse tree may get, counting parentheses, function calls, unary operators and
+// each operand of a chain like x + x + x. Both parsing and evaluation recurse along the
+// tree, and overflowing the stack is an unrecoverable trap in wasm
+const MAX_DEPTH: usize = 128;
+
+// Recursive descent parser. Grammar, lowest precedence first:
+//   expr    := term (('+' | '-') term)*
+//   term    := unary (('*' | '/') unary)*
+//   unary   := '-' unary | '+' unary | power
+//   power   := primary ('^' unary)?          (right associative, so 2^3^2 = 2^9)
+//   primary := number | constant | variable | function '(' expr ')' | '(' expr ')'
+struct Parser<'a> {
+    tokens: &'a [(usize, Token)],
+    pos: usize,
+    source_len: usize,
+    // Current depth of the parse tree, at most MAX_DEPTH
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map(|(offset, _)| *offset)
+            .unwrap_or(self.source_len)
+    }
+
+    fn advance(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.pos).map(|(_, token)| token);
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token, description: &str) -> Result<(), String> {
+        let offset = self.offset();
+        match self.advance() {
+            Some(token) if *token == expected => Ok(()),
+            Some(_) => Err(format!("Expected {} at position {}", description, offset)),
+            None => Err(format!("Expected {} at end of expression", description)),
+        }
+    }
+
+    // Runs `parse` one level deeper in the tree
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        if self.depth >= MAX_DEPTH {
+            return Err(format!(
+                "Expression is nested more than {} levels deep at position {}",
+                MAX_DEPTH,
+                self.offset()
+            ));
+        }
+        self.depth += 1;
+        let return_var = parse(self);
+        self.depth -= 1;
+        return_var
+    }
+
+    fn parse_expr(&mut self) -> Result<Node, String> {
+        let mut node = self.parse_term()?;
+        // Each operand makes the left-nested tree one level deeper
+        let depth = self.depth;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus) => BinaryOp::Add,
+                Some(Token::Minus) => BinaryOp::Sub,
+                _ => break,
+            };
+            self.advance();
+            let rhs = self.nested(Self::parse_term);
+            self.depth += 1;
+            node = Node::Binary(op, Box::new(node), Box::new(rhs?));
+        }
+        self.depth = depth;
+        Ok(node)
+    }
+
+    fn parse_term(&mut self) -> Result<Node, String> {
+        let mut node = self.parse_unary()?;
+        let depth = self.depth;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Star) => BinaryOp::Mul,
+                Some(Token::Slash) => BinaryOp::Div,
+                _ => break,
+            };
+            self.advance();
+            let rhs = self.nested(Self::parse_unary);
+            self.depth += 1;
+            node = Node::Binary(op, Box::new(node), Box::new(rhs?));
+        }
+        self.depth = depth;
+        Ok(node)
+    }
+
+    fn parse_unary(&mut self) -> Result<Node, String> {
+        match self.peek() {
+            Some(Token::Minus) => {
+                self.advance();
+                Ok(Node::Neg(Box::new(self.nested(Self::parse_unary)?)))
+            }
+            Some(Token::Plus) => {
+                self.advance();
+                self.nested(Self::parse_unary)
+            }
+            _ => self.parse_power(),
+        }
+    }
+
+    fn parse_power(&mut self) -> Result<Node, String> {
+        let base = self.parse_primary()?;
+        if let Some(Token::Caret) = self.peek() {
+            self.advance();
+            let exponent = self.nested(Self::parse_unary)?;
+            return Ok(Node::Binary(BinaryOp::Pow, Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn parse_primary(&mut self) -> Result<Node, String> {
+        let offset = self.offset();
+        match self.advance() {
+            Some(Token::Number(v)) => Ok(Node::Number(*v)),
+            Some(Token::LeftParen) => {
+                let node = self.nested(Self::parse_expr)?;
+                self.expect(Token::RightParen, "')'")?;
+                Ok(node)
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "x" => Ok(Node::Var(Variable::X)),
+                "z" => Ok(Node::Var(Variable::Z)),
+                "t" => Ok(Node::Var(Variable::T)),
+                "pi" => Ok(Node::Number(std::f32::consts::PI)),
+                "e" => Ok(Node::Number(std::f32::consts::E)),
+                _ => {
+                    let func = Function::from_name(name).ok_or_else(|| {
+                        format!("Unknown identifier '{}' at position {}", name, offset)
+                    })?;
+                    self.expect(Token::LeftParen, &format!("'(' after '{}'", name))?;
+                    let arg = self.nested(Self::parse_expr)?;
+                    self.expect(Token::RightParen, "')'")?;
+                    Ok(Node::Call(func, Box::new(arg)))
+                }
+            },
+            Some(_) => Err(format!("Unexpected token at position {}", offset)),
+            None => Err(String::from("Unexpected end of expression")),
+        }
+    }
+}
+
+/// A parsed surface expression y = f(x, z, t)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    source: String,
+    root: Node,
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        if tokens.is_empty() {
+            return Err(String::from("Expression is empty"));
+        }
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+            source_len: source.len(),
+            depth: 0,
+        };
+        let root = parser.parse_expr()?;
+        if parser.pos < tokens.len() {
+            return Err(format!("Unexpected token at position {}", parser.offset()));
+        }
+        Ok(Self {
+            source: source.to_string(),
+            root,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn eval(&self, x: f32, z: f32, t: f32) -> f32 {
+        self.root.eval(x, z, t)
+    }
+
+    /// Whether the expression depends on t, and so changes from frame to frame
+    pub fn uses_time(&self) -> bool {
+        self.root.uses_time()
+    }
+}
+
+impl std::str::FromStr for Expression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Expression::parse(s)
+    }
+}
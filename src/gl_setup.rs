@@ -1,63 +1,287 @@
+use std::any::Any;
+use wasm_bindgen::convert::FromWasmAbi;
 use wasm_bindgen::JsCast;
 use wasm_bindgen::JsValue;
 use wasm_bindgen::prelude::Closure;
 use web_sys::*;
 use web_sys::WebGlRenderingContext as GL;
+use crate::app_state::SharedAppState;
+use crate::backend::RenderBackend;
+use crate::error::Error;
 
-pub fn initialize_webgl_context() -> Result<WebGlRenderingContext, JsValue>
+// Options accepted by the Client constructor, all optional:
+// { antialias: bool, alpha: bool, preserveDrawingBuffer: bool, backgroundColor: [r, g, b, a?] }
+pub struct ContextOptions {
+    pub antialias: bool,
+    pub alpha: bool,
+    pub preserve_drawing_buffer: bool,
+    pub background_color: [f32; 4],
+}
+
+impl Default for ContextOptions {
+    fn default() -> Self {
+        Self {
+            antialias: true,
+            alpha: true,
+            preserve_drawing_buffer: false,
+            background_color: [0.0, 0.0, 0.0, 1.0],
+        }
+    }
+}
+
+impl ContextOptions {
+    pub fn from_js(options: &JsValue) -> Result<Self, Error> {
+        let mut return_var = Self::default();
+        if options.is_undefined() || options.is_null() {
+            return Ok(return_var);
+        }
+        if !options.is_object() {
+            return Err(Error::InvalidOption(String::from("Client options must be an object")));
+        }
+
+        let read_bool = |name: &str, default: bool| -> Result<bool, Error> {
+            let value = js_sys::Reflect::get(options, &JsValue::from_str(name))?;
+            if value.is_undefined() {
+                return Ok(default);
+            }
+            value
+                .as_bool()
+                .ok_or_else(|| Error::InvalidOption(format!("Client option '{}' must be a boolean", name)))
+        };
+        return_var.antialias = read_bool("antialias", return_var.antialias)?;
+        return_var.alpha = read_bool("alpha", return_var.alpha)?;
+        return_var.preserve_drawing_buffer = read_bool("preserveDrawingBuffer", return_var.preserve_drawing_buffer)?;
+
+        let background_color = js_sys::Reflect::get(options, &JsValue::from_str("backgroundColor"))?;
+        if !background_color.is_undefined() {
+            return_var.background_color = parse_color(&background_color)
+                .ok_or_else(|| Error::InvalidOption(String::from("Client option 'backgroundColor' must be an array of 3 or 4 numbers between 0 and 1")))?;
+        }
+        Ok(return_var)
+    }
+}
+
+fn parse_color(value: &JsValue) -> Option<[f32; 4]> {
+    let array: &js_sys::Array = value.dyn_ref()?;
+    if array.length() != 3 && array.length() != 4 {
+        return None;
+    }
+    let mut color = [1.0; 4];
+    for (i, channel) in color.iter_mut().enumerate().take(array.length() as usize) {
+        let v = array.get(i as u32).as_f64()? as f32;
+        if !(0.0..=1.0).contains(&v) {
+            return None;
+        }
+        *channel = v;
+    }
+    Some(color)
+}
+
+// Accepts either an HtmlCanvasElement or the id of one
+pub fn find_canvas(canvas: &JsValue) -> Result<HtmlCanvasElement, Error>
+{
+    if let Some(canvas) = canvas.dyn_ref::<HtmlCanvasElement>() {
+        return Ok(canvas.clone());
+    }
+    let id = canvas
+        .as_string()
+        .ok_or_else(|| Error::Context(String::from("Expected an HTMLCanvasElement or the id of one")))?;
+
+    let document = window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| Error::Context(String::from("No document available to look up the canvas in")))?;
+    let element = document
+        .get_element_by_id(&id)
+        .ok_or_else(|| Error::Context(format!("No element with id '{}' found", id)))?;
+    element
+        .dyn_into::<HtmlCanvasElement>()
+        .map_err(|_| Error::Context(format!("Element '{}' is not a <canvas>", id)))
+}
+
+pub fn initialize_webgl_context(canvas: &HtmlCanvasElement, options: &ContextOptions) -> Result<WebGlRenderingContext, Error>
 {
-    let window = window().unwrap();
-    let document = window.document().unwrap();
-    let canvas = document.get_element_by_id("rustCanvas").unwrap();
-    let canvas: web_sys::HtmlCanvasElement = canvas.dyn_into::<web_sys::HtmlCanvasElement>()?;
-    let gl: WebGlRenderingContext = canvas.get_context("webgl")?.unwrap().dyn_into::<GL>()?;
+    let context_attributes = js_sys::Object::new();
+    js_sys::Reflect::set(&context_attributes, &"antialias".into(), &options.antialias.into())?;
+    js_sys::Reflect::set(&context_attributes, &"alpha".into(), &options.alpha.into())?;
+    js_sys::Reflect::set(&context_attributes, &"preserveDrawingBuffer".into(), &options.preserve_drawing_buffer.into())?;
 
+    let gl: WebGlRenderingContext = canvas
+        .get_context_with_context_options("webgl", &context_attributes)?
+        .ok_or_else(|| Error::Context(String::from("WebGL is not supported by this browser or is disabled")))?
+        .dyn_into::<GL>()
+        .map_err(|_| Error::Context(String::from("The canvas already has a non-WebGL rendering context")))?;
+
+    configure_context(&gl);
+    let [red, green, blue, alpha] = options.background_color;
+    gl.clear_color(red, green, blue, alpha);
+
+    Ok(gl)
+}
+
+// Event listeners added to the canvas, removed again when this is dropped so the
+// closures and the AppState they hold are freed with the Client
+pub struct EventListeners {
+    canvas: HtmlCanvasElement,
+    listeners: Vec<Listener>,
+}
+
+struct Listener {
+    event: &'static str,
+    function: js_sys::Function,
+    // The Closure behind `function`, it must outlive the listener
+    _closure: Box<dyn Any>,
+}
+
+impl EventListeners {
+    fn new(canvas: &HtmlCanvasElement) -> Self {
+        Self {
+            canvas: canvas.clone(),
+            listeners: Vec::new(),
+        }
+    }
+
+    fn add<E: FromWasmAbi + 'static>(&mut self, event: &'static str, handler: impl FnMut(E) + 'static) -> Result<(), JsValue> {
+        let closure = Closure::wrap(Box::new(handler) as Box<dyn FnMut(E)>);
+        let function: js_sys::Function = closure.as_ref().clone().unchecked_into();
+        self.canvas.add_event_listener_with_callback(event, &function)?;
+        self.listeners.push(Listener {
+            event,
+            function,
+            _closure: Box::new(closure),
+        });
+        Ok(())
+    }
+}
+
+impl Drop for EventListeners {
+    fn drop(&mut self) {
+        for listener in &self.listeners {
+            let _ = self.canvas.remove_event_listener_with_callback(listener.event, &listener.function);
+        }
+    }
+}
+
+pub fn attach_event_listeners(canvas: &HtmlCanvasElement, app_state: &SharedAppState) -> Result<EventListeners, Error>
+{
+    let mut return_var = EventListeners::new(canvas);
     // Attach mouse event handlers
-    attach_mouse_down_handler(&canvas)?;
-    attach_mouse_up_handler(&canvas)?;
-    attach_mouse_move_handler(&canvas)?;
-    
+    attach_mouse_down_handler(&mut return_var, app_state)?;
+    attach_mouse_up_handler(&mut return_var, app_state)?;
+    attach_mouse_move_handler(&mut return_var, app_state)?;
+    attach_mouse_wheel_handler(&mut return_var, app_state)?;
+    attach_context_menu_handler(&mut return_var)?;
+    // Touch and pen input, the mouse keeps using the handlers above
+    attach_touch_handlers(&mut return_var, canvas, app_state)?;
+    Ok(return_var)
+}
+
+// Render state shared by the browser context and the headless renderer
+pub fn configure_context<B: RenderBackend>(gl: &B)
+{
     // Enable alpha blending
     gl.enable(GL::BLEND);
     gl.blend_func(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA);
+    // The 3D graph can overlap itself
+    gl.enable(GL::DEPTH_TEST);
     gl.clear_color(0.0, 0.0, 0.0, 1.0); // RGBA
     gl.clear_depth(1.0);
-
-    Ok(gl)
 }
 
-fn attach_mouse_down_handler(canvas: &HtmlCanvasElement) -> Result<(), JsValue>
+fn attach_mouse_down_handler(listeners: &mut EventListeners, app_state: &SharedAppState) -> Result<(), JsValue>
 {
+    let app_state = app_state.clone();
     let handler = move |event: web_sys::MouseEvent| {
-        crate::app_state::update_mouse_down(event.client_x() as f32 , event.client_y() as f32 , true);
+        // Right button or shift + drag pans instead of rotating
+        let pan = event.button() == 2 || event.shift_key();
+        // Offsets are relative to the canvas, client coordinates would be off wherever it is on the page
+        app_state.borrow_mut().update_mouse_down(event.offset_x() as f32, event.offset_y() as f32, true, pan);
     };
-    let handler = Closure::wrap(Box::new(handler) as Box<dyn FnMut(_)>); 
-    canvas.add_event_listener_with_callback("mousedown", handler.as_ref().unchecked_ref())?;
-    // Ensure that rust doesnt drop the handler
-    handler.forget();
-    Ok(())
+    listeners.add("mousedown", handler)
 }
 
-fn attach_mouse_up_handler(canvas: &HtmlCanvasElement) -> Result<(), JsValue>
+fn attach_mouse_up_handler(listeners: &mut EventListeners, app_state: &SharedAppState) -> Result<(), JsValue>
 {
+    let app_state = app_state.clone();
     let handler = move |event: web_sys::MouseEvent| {
-        crate::app_state::update_mouse_down(event.client_x() as f32 , event.client_y() as f32 , false);
+        app_state.borrow_mut().update_mouse_down(event.offset_x() as f32, event.offset_y() as f32, false, false);
     };
-    let handler = Closure::wrap(Box::new(handler) as Box<dyn FnMut(_)>); 
-    canvas.add_event_listener_with_callback("mouseup", handler.as_ref().unchecked_ref())?;
-    // Ensure that rust doesnt drop the handler
-    handler.forget();
-    Ok(())
+    listeners.add("mouseup", handler)
 }
 
-fn attach_mouse_move_handler(canvas: &HtmlCanvasElement) -> Result<(), JsValue>
+fn attach_mouse_move_handler(listeners: &mut EventListeners, app_state: &SharedAppState) -> Result<(), JsValue>
 {
+    let app_state = app_state.clone();
     let handler = move |event: web_sys::MouseEvent| {
-        crate::app_state::update_mouse_position(event.client_x() as f32 , event.client_y() as f32);
+        app_state.borrow_mut().update_mouse_position(event.offset_x() as f32, event.offset_y() as f32);
+    };
+    listeners.add("mousemove", handler)
+}
+
+fn attach_mouse_wheel_handler(listeners: &mut EventListeners, app_state: &SharedAppState) -> Result<(), JsValue>
+{
+    let app_state = app_state.clone();
+    let handler = move |event: web_sys::WheelEvent| {
+        // Keep the page from scrolling while zooming
+        event.prevent_default();
+        // Normalize line and page deltas to pixels
+        let delta_y = match event.delta_mode() {
+            WheelEvent::DOM_DELTA_LINE => event.delta_y() * 16.,
+            WheelEvent::DOM_DELTA_PAGE => event.delta_y() * 800.,
+            _ => event.delta_y(),
+        };
+        app_state.borrow_mut().update_mouse_wheel(delta_y as f32);
     };
-    let handler = Closure::wrap(Box::new(handler) as Box<dyn FnMut(_)>); 
-    canvas.add_event_listener_with_callback("mousemove", handler.as_ref().unchecked_ref())?;
-    // Ensure that rust doesnt drop the handler
-    handler.forget();
+    listeners.add("wheel", handler)
+}
+
+fn attach_context_menu_handler(listeners: &mut EventListeners) -> Result<(), JsValue>
+{
+    // Right-drag is used for panning, so suppress the browser menu on the canvas
+    let handler = move |event: web_sys::Event| {
+        event.prevent_default();
+    };
+    listeners.add("contextmenu", handler)
+}
+
+fn attach_touch_handlers(listeners: &mut EventListeners, canvas: &HtmlCanvasElement, app_state: &SharedAppState) -> Result<(), JsValue>
+{
+    // Stop the browser from scrolling or zooming the page on touch gestures
+    canvas.style().set_property("touch-action", "none")?;
+
+    let down_state = app_state.clone();
+    let down_canvas = canvas.clone();
+    let on_down = move |event: web_sys::PointerEvent| {
+        if event.pointer_type() == "mouse" {
+            return;
+        }
+        // No emulated mouse events or text selection for touch and pen
+        event.prevent_default();
+        // Keep receiving moves when the finger leaves the canvas
+        let _ = down_canvas.set_pointer_capture(event.pointer_id());
+        down_state.borrow_mut().update_touch_down(event.pointer_id(), event.offset_x() as f32, event.offset_y() as f32);
+    };
+
+    let move_state = app_state.clone();
+    let on_move = move |event: web_sys::PointerEvent| {
+        if event.pointer_type() == "mouse" {
+            return;
+        }
+        move_state.borrow_mut().update_touch_move(event.pointer_id(), event.offset_x() as f32, event.offset_y() as f32);
+    };
+
+    listeners.add("pointerdown", on_down)?;
+    listeners.add("pointermove", on_move)?;
+    // A contact that is lifted, cancelled by the browser or loses its capture no longer
+    // takes part in gestures, otherwise it would leave a stale pinch partner behind
+    for event in ["pointerup", "pointercancel", "lostpointercapture"] {
+        let up_state = app_state.clone();
+        let on_up = move |event: web_sys::PointerEvent| {
+            if event.pointer_type() == "mouse" {
+                return;
+            }
+            up_state.borrow_mut().update_touch_up(event.pointer_id());
+        };
+        listeners.add(event, on_up)?;
+    }
     Ok(())
-} 
+}
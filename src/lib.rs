@@ -1,12 +1,19 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use web_sys::WebGlRenderingContext as GL;
 
-mod gl_setup;
-mod shaders;
-mod programs;
-mod common_funcs;
-mod app_state;
-mod constants;
+pub mod backend;
+pub mod gl_setup;
+pub mod shaders;
+pub mod programs;
+pub mod common_funcs;
+pub mod app_state;
+pub mod constants;
+pub mod expression;
+pub mod error;
+pub mod shader_diagnostics;
+pub mod plot_domain;
 
 #[wasm_bindgen]
 extern "C" {
@@ -18,33 +25,224 @@ extern "C" {
 #[wasm_bindgen]
 pub struct Client {
     gl: GL,
-    _program_color_2d: programs::Color2D,
-    _program_color_2d_gradient: programs::Color2DGradient,
-    program_graph_3d: programs::Graph3D,
+    app_state: app_state::SharedAppState,
+    // Removed from the canvas when the Client is dropped
+    _event_listeners: gl_setup::EventListeners,
+    _program_color_2d: programs::Color2D<GL>,
+    _program_color_2d_gradient: programs::Color2DGradient<GL>,
+    program_graph_3d: programs::Graph3D<GL>,
+    surface: expression::Expression,
+    // Drawn on the GPU when set, `surface` then holds the same function
+    builtin_surface: Option<programs::BuiltinSurface>,
+    domain: plot_domain::PlotDomain,
+    // The fog fades into it
+    background_color: [f32; 3],
+    // Reused every frame
+    y_vals: RefCell<Vec<f32>>,
+    // Set when the uploaded heights are out of date. Surfaces that depend on t are
+    // recomputed every frame regardless
+    heights_dirty: Cell<bool>,
 }
 
 #[wasm_bindgen]
 impl Client {
+    /// `canvas` is an HTMLCanvasElement or its id, `options` is an optional object
+    /// { antialias, alpha, preserveDrawingBuffer, backgroundColor: [r, g, b, a] }
     #[wasm_bindgen(constructor)]
-    pub fn new() -> Self {
+    pub fn new(canvas: JsValue, options: JsValue) -> Result<Client, JsValue> {
         console_error_panic_hook::set_once();
-        let gl = gl_setup::initialize_webgl_context().unwrap();
-        Client {
-            _program_color_2d: programs::Color2D::new(&gl),
-            _program_color_2d_gradient: programs::Color2DGradient::new(&gl),
-            program_graph_3d: programs::Graph3D::new(&gl),
+        let canvas = gl_setup::find_canvas(&canvas)?;
+        let options = gl_setup::ContextOptions::from_js(&options)?;
+        let app_state = Rc::new(RefCell::new(app_state::AppState::new()));
+        let gl = gl_setup::initialize_webgl_context(&canvas, &options)?;
+        let event_listeners = gl_setup::attach_event_listeners(&canvas, &app_state)?;
+        Ok(Client {
+            app_state,
+            _event_listeners: event_listeners,
+            _program_color_2d: programs::Color2D::new(&gl)?,
+            _program_color_2d_gradient: programs::Color2DGradient::new(&gl)?,
+            program_graph_3d: programs::Graph3D::new(&gl)?,
+            surface: expression::Expression::parse(constants::DEFAULT_SURFACE_EXPRESSION)
+                .expect("DEFAULT_SURFACE_EXPRESSION is a valid expression"),
+            builtin_surface: None,
+            domain: plot_domain::PlotDomain::default(),
+            background_color: [options.background_color[0], options.background_color[1], options.background_color[2]],
+            y_vals: RefCell::new(Vec::new()),
+            heights_dirty: Cell::new(true),
             gl,
+        })
+    }
+
+    pub fn surface_expression(&self) -> String {
+        self.surface.source().to_string()
+    }
+
+    /// How quickly the graph stops spinning after a drag is released, as a decay
+    /// rate per second. 0 keeps it spinning forever
+    pub fn set_rotation_damping(&self, damping: f32) -> Result<(), JsValue> {
+        if !damping.is_finite() || damping < 0. {
+            return Err(error::Error::InvalidOption(String::from("Rotation damping must be a non-negative number")).into());
         }
+        self.app_state.borrow_mut().rotation_damping = damping;
+        Ok(())
+    }
+
+    /// Switches between the perspective and the orthographic camera, animated over a few frames
+    pub fn set_orthographic(&self, orthographic: bool) {
+        self.app_state.borrow_mut().orthographic = orthographic;
+    }
+
+    /// Snaps the camera to "top", "front", "side", "isometric" or "reset"
+    pub fn set_view(&self, preset: &str) -> Result<(), JsValue> {
+        let preset: app_state::ViewPreset = preset.parse().map_err(error::Error::InvalidOption)?;
+        self.app_state.borrow_mut().set_view_preset(preset);
+        Ok(())
+    }
+
+    /// Zooms and centers the camera so the current surface fills the display area
+    pub fn fit_to_data(&self) {
+        let grid_size = self.program_graph_3d.grid_size();
+        let mut state = self.app_state.borrow_mut();
+        let positions = common_funcs::get_grid_positions(grid_size);
+        let y_vals = common_funcs::get_updated_y_values(state.time, grid_size, &self.domain, &self.surface);
+        state.fit_to_data(&positions, &y_vals);
+    }
+
+    pub fn grid_size_x(&self) -> usize {
+        self.program_graph_3d.grid_size().x()
+    }
+
+    pub fn grid_size_z(&self) -> usize {
+        self.program_graph_3d.grid_size().z()
+    }
+
+    /// Number of grid cells along x and z, at most constants::MAX_GRID_CELLS in total
+    pub fn set_grid_size(&mut self, x: usize, z: usize) -> Result<(), JsValue> {
+        let grid_size = common_funcs::GridSize::new(x, z).map_err(error::Error::InvalidOption)?;
+        self.program_graph_3d.set_grid_size(&self.gl, grid_size)?;
+        self.heights_dirty.set(true);
+        Ok(())
+    }
+
+    /// How the lighting normals are computed: "forward" (faceted), "central" (smooth, the
+    /// default) or "area" (area-weighted average of the adjacent triangles)
+    pub fn set_normal_mode(&mut self, mode: &str) -> Result<(), JsValue> {
+        let mode: common_funcs::NormalMode = mode.parse().map_err(error::Error::InvalidOption)?;
+        self.program_graph_3d.set_normal_mode(mode);
+        self.heights_dirty.set(true);
+        Ok(())
+    }
+
+    /// Colors the surface by height, from dark blue at the bottom of the y range to yellow at the top
+    pub fn set_colormap(&mut self, enabled: bool) -> Result<(), JsValue> {
+        let style = programs::SurfaceStyle { colormap: enabled, ..self.program_graph_3d.style() };
+        self.program_graph_3d
+            .set_style(&self.gl, style)
+            .map_err(JsValue::from)
+    }
+
+    /// Fades the far side of the surface into the background color, for a sense of depth
+    pub fn set_fog(&mut self, enabled: bool) -> Result<(), JsValue> {
+        let fog = if enabled { Some(self.background_color) } else { None };
+        let style = programs::SurfaceStyle { fog, ..self.program_graph_3d.style() };
+        self.program_graph_3d
+            .set_style(&self.gl, style)
+            .map_err(JsValue::from)
+    }
+
+    /// "lambert" (per vertex, the default), "phong" or "blinn-phong", which are lit per pixel
+    /// with specular highlights
+    pub fn set_lighting_model(&mut self, model: &str) -> Result<(), JsValue> {
+        let lighting: programs::LightingModel = model.parse().map_err(error::Error::InvalidOption)?;
+        let style = programs::SurfaceStyle { lighting, ..self.program_graph_3d.style() };
+        self.program_graph_3d
+            .set_style(&self.gl, style)
+            .map_err(JsValue::from)
+    }
+
+    /// Direction towards the light, relative to the viewer: x right, y up, z towards the viewer
+    pub fn set_light_direction(&mut self, x: f32, y: f32, z: f32) -> Result<(), JsValue> {
+        let light = programs::Light { direction: [x, y, z], ..self.program_graph_3d.light() };
+        self.program_graph_3d.set_light(light).map_err(JsValue::from)
+    }
+
+    /// Color of the light, white by default
+    pub fn set_light_color(&mut self, red: f32, green: f32, blue: f32) -> Result<(), JsValue> {
+        let light = programs::Light { color: [red, green, blue], ..self.program_graph_3d.light() };
+        self.program_graph_3d.set_light(light).map_err(JsValue::from)
+    }
+
+    /// Color of the ambient light, scaled by the ambient coefficient. Red by default
+    pub fn set_ambient_color(&mut self, red: f32, green: f32, blue: f32) -> Result<(), JsValue> {
+        let light = programs::Light { ambient_color: [red, green, blue], ..self.program_graph_3d.light() };
+        self.program_graph_3d.set_light(light).map_err(JsValue::from)
+    }
+
+    /// How much of the ambient color and of the light is reflected as ambient, diffuse and
+    /// specular light (0.1, 1 and 0.5 by default), and the specular exponent (32). The
+    /// lambert model has no highlights
+    pub fn set_light_coefficients(&mut self, ambient: f32, diffuse: f32, specular: f32, shininess: f32) -> Result<(), JsValue> {
+        let light = programs::Light { ambient, diffuse, specular, shininess, ..self.program_graph_3d.light() };
+        self.program_graph_3d.set_light(light).map_err(JsValue::from)
     }
 
     pub fn update(&self, time: f32, height:f32, width: f32) -> Result<(), JsValue> {
-        app_state::update_dynamic_data(time, height, width);
+        self.app_state.borrow_mut().update_dynamic_data(time, height, width);
+        Ok(())
+    }
+
+    /// Replaces the function drawn by the 3D graph, e.g. "sin(x) * cos(z + t * 0.001)"
+    /// x and z range over the plot domain, t is the time in milliseconds passed to `update`
+    pub fn set_surface_expression(&mut self, expression: &str) -> Result<(), JsValue> {
+        self.surface = expression::Expression::parse(expression)
+            .map_err(|e| error::Error::InvalidOption(format!("Invalid surface expression: {}", e)))?;
+        self.builtin_surface = None;
+        self.heights_dirty.set(true);
+        Ok(())
+    }
+
+    /// Draws "ripple", "waves" or "saddle" with the heights computed on the GPU, which is
+    /// much faster for large grids. The automatic y range is the exact range of the function
+    /// over the domain rather than of the sampled heights
+    pub fn set_builtin_surface(&mut self, name: &str) -> Result<(), JsValue> {
+        let builtin_surface: programs::BuiltinSurface = name.parse().map_err(error::Error::InvalidOption)?;
+        // tests/golden.rs parses every one of them
+        self.surface = expression::Expression::parse(builtin_surface.expression())
+            .expect("BuiltinSurface::expression returns valid expressions");
+        self.builtin_surface = Some(builtin_surface);
+        self.heights_dirty.set(true);
         Ok(())
     }
 
+    /// Ranges of x and z the surface is sampled over, [-2pi, 2pi] by default.
+    /// They are stretched to the same square on screen
+    pub fn set_domain(&mut self, x_min: f32, x_max: f32, z_min: f32, z_max: f32) -> Result<(), JsValue> {
+        self.domain.x = plot_domain::AxisRange::new(x_min, x_max).map_err(error::Error::InvalidOption)?;
+        self.domain.z = plot_domain::AxisRange::new(z_min, z_max).map_err(error::Error::InvalidOption)?;
+        self.heights_dirty.set(true);
+        Ok(())
+    }
+
+    /// Heights drawn from the bottom to the top of the plot, fitted to the surface by default
+    pub fn set_y_range(&mut self, y_min: f32, y_max: f32) -> Result<(), JsValue> {
+        self.domain.y = Some(plot_domain::AxisRange::new(y_min, y_max).map_err(error::Error::InvalidOption)?);
+        self.heights_dirty.set(true);
+        Ok(())
+    }
+
+    /// Fits the y range to the lowest and highest point of the surface, every frame. This is the default
+    pub fn set_auto_y_range(&mut self) {
+        self.domain.y = None;
+        self.heights_dirty.set(true);
+    }
+
+    /// Fails with a JS Error when the WebGL context has been lost, or the surface cannot be drawn
     pub fn render(&self) -> Result<(), JsValue> {
+        if self.gl.is_context_lost() {
+            return Err(error::Error::Context(String::from("The WebGL context was lost")).into());
+        }
         self.gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
-        let curr_state = app_state::get_curr_state();
+        let curr_state = self.app_state.borrow();
         // self._program_color_2d.render(
         //     &self.gl,
         //     curr_state.control_bottom,
@@ -63,18 +261,55 @@ impl Client {
         //     curr_state.canvas_height,
         //     curr_state.canvas_width,
         // );
+        let builtin_y_range = self.builtin_surface.and_then(|builtin_surface| builtin_surface.y_range(&self.domain));
+        if let (Some(builtin_surface), Some(y_range)) = (self.builtin_surface, builtin_y_range) {
+            self.program_graph_3d.render_builtin(
+                &self.gl,
+                curr_state.control_bottom + constants::GRAPH_MARGIN,
+                curr_state.control_top - constants::GRAPH_MARGIN,
+                curr_state.control_left + constants::GRAPH_MARGIN,
+                curr_state.control_right - constants::GRAPH_MARGIN,
+                curr_state.canvas_height,
+                curr_state.canvas_width,
+                &curr_state.rotation,
+                curr_state.zoom,
+                curr_state.pan_x,
+                curr_state.pan_y,
+                curr_state.orthographic_blend,
+                builtin_surface,
+                curr_state.time,
+                &self.domain,
+                y_range,
+            );
+            return Ok(());
+        }
+        if self.heights_dirty.get() || self.surface.uses_time() {
+            let mut y_vals = self.y_vals.borrow_mut();
+            common_funcs::update_y_values(
+                curr_state.time,
+                self.program_graph_3d.grid_size(),
+                &self.domain,
+                &self.surface,
+                &mut y_vals,
+            );
+            self.program_graph_3d.set_y_values(&self.gl, &y_vals)?;
+            // Only once uploaded, so a failed upload is retried next frame
+            self.heights_dirty.set(false);
+        }
         self.program_graph_3d.render(
             &self.gl,
-            curr_state.control_bottom + 20.,
-            curr_state.control_top - 20.,
-            curr_state.control_left + 20.,
-            curr_state.control_right - 20.,
+            curr_state.control_bottom + constants::GRAPH_MARGIN,
+            curr_state.control_top - constants::GRAPH_MARGIN,
+            curr_state.control_left + constants::GRAPH_MARGIN,
+            curr_state.control_right - constants::GRAPH_MARGIN,
             curr_state.canvas_height,
             curr_state.canvas_width,
-            curr_state.rotation_angle_x_axis,
-            curr_state.rotation_angle_y_axis,
-            &common_funcs::get_updated_y_values(curr_state.time),
-        );
+            &curr_state.rotation,
+            curr_state.zoom,
+            curr_state.pan_x,
+            curr_state.pan_y,
+            curr_state.orthographic_blend,
+        )?;
         Ok(())
     }
 }
@@ -0,0 +1,81 @@
+// Maps the data coordinates of a plot to the normalized coordinates Graph3D draws in
+// The grid always spans [-1, 1] along x and z on screen, whatever ranges the data covers.
+// Heights are mapped from the y range to [-1, 1], by default the range of the sampled heights
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AxisRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl AxisRange {
+    pub fn new(min: f32, max: f32) -> Result<Self, String> {
+        // The width must be finite too, normalize divides by it
+        if !min.is_finite() || !max.is_finite() || min >= max || !(max - min).is_finite() {
+            return Err(format!("Invalid range [{}, {}], expected finite bounds with min < max", min, max));
+        }
+        Ok(AxisRange { min, max })
+    }
+
+    // Value at `fraction` of the way from min to max
+    pub fn lerp(&self, fraction: f32) -> f32 {
+        self.min + fraction * (self.max - self.min)
+    }
+
+    // Maps [min, max] to [-1, 1]
+    pub fn normalize(&self, value: f32) -> f32 {
+        2. * ((value - self.min) / (self.max - self.min)) - 1.
+    }
+
+    // Smallest range holding every finite value, None if there are none or they are too far apart
+    pub fn enclosing(values: &[f32]) -> Option<Self> {
+        let (min, max) = values
+            .iter()
+            .filter(|value| value.is_finite())
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), value| (min.min(*value), max.max(*value)));
+        if min > max {
+            return None;
+        }
+        if min == max {
+            // Flat surface, keep it in the middle
+            return Some(AxisRange { min: min - 1., max: max + 1. });
+        }
+        AxisRange::new(min, max).ok()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlotDomain {
+    pub x: AxisRange,
+    pub z: AxisRange,
+    // None fits the range to the sampled heights
+    pub y: Option<AxisRange>,
+}
+
+impl Default for PlotDomain {
+    // x and z over [-2pi, 2pi], y fitted to the surface
+    fn default() -> Self {
+        let two_pi = 2. * std::f32::consts::PI;
+        PlotDomain {
+            x: AxisRange { min: -two_pi, max: two_pi },
+            z: AxisRange { min: -two_pi, max: two_pi },
+            y: None,
+        }
+    }
+}
+
+impl PlotDomain {
+    // Maps sampled heights to display heights in place
+    pub fn normalize_y(&self, y_vals: &mut [f32]) {
+        let y_range = match self.y {
+            Some(y_range) => y_range,
+            None => match AxisRange::enclosing(y_vals) {
+                Some(y_range) => y_range,
+                None => return,
+            },
+        };
+        for y in y_vals.iter_mut() {
+            *y = y_range.normalize(*y);
+        }
+    }
+}
@@ -1,21 +1,25 @@
-use wasm_bindgen::JsCast;
 use web_sys::WebGlRenderingContext as GL;
-use web_sys::*;
-use js_sys::WebAssembly;
+use crate::backend::buffers::VertexBuffer;
+use crate::backend::program::ShaderProgram;
+use crate::backend::RenderBackend;
 use crate::common_funcs as cf;
+use crate::error::Error;
 
-pub struct Color2D{
-    program: WebGlProgram,
-    rect_vertices_len: usize,
-    rect_vertices_buffer: WebGlBuffer,
-    u_color: WebGlUniformLocation,
-    u_opacity: WebGlUniformLocation,
-    u_transform: WebGlUniformLocation,
+pub struct Color2D<B: RenderBackend> {
+    program: ShaderProgram<B>,
+    rect_vertices_buffer: VertexBuffer<B>,
+    a_position: u32,
 }
 
-impl Color2D{
-    pub fn new(gl: &WebGlRenderingContext) -> Self {
-        let program = cf::link_program(gl, crate::shaders::vertex::color_2d::SHADER, crate::shaders::fragment::color_2d::SHADER).unwrap();
+impl<B: RenderBackend> Color2D<B> {
+    pub fn new(gl: &B) -> Result<Self, Error> {
+        let program = ShaderProgram::new(
+            gl,
+            crate::shaders::vertex::color_2d::SHADER,
+            crate::shaders::fragment::color_2d::SHADER,
+            &[],
+            &["aPosition"],
+        )?;
 
         let vertices_rect: [f32; 12] = [
             0., 1., // x, y
@@ -26,32 +30,22 @@ impl Color2D{
             1., 0., // x, y
         ];
 
-        let memory_buffer = wasm_bindgen::memory()
-            .dyn_into::<WebAssembly::Memory>()
-            .unwrap()
-            .buffer();
-        
-        let vertices_location = vertices_rect.as_ptr() as u32 / 4;
-        let vert_array = js_sys::Float32Array::new(&memory_buffer).subarray(
-            vertices_location, 
-            vertices_location + vertices_rect.len() as u32
-        );
-
-        let buffer_rect = gl.create_buffer().ok_or("failed to create buffer").unwrap();
-        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&buffer_rect));
-        gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &vert_array, GL::STATIC_DRAW);
-        Self {
-            // These are all variables that are used in the shaders
-            u_color: gl.get_uniform_location(&program, "uColor").unwrap(),
-            u_opacity: gl.get_uniform_location(&program, "uOpacity").unwrap(),
-            u_transform: gl.get_uniform_location(&program, "uTransform").unwrap(),
-            rect_vertices_len: vertices_rect.len(),
+        // Two values per vertex (would be 3 for 3D)
+        let buffer_rect = VertexBuffer::with_data(gl, 2, &vertices_rect, GL::STATIC_DRAW)?;
+        Ok(Self {
+            a_position: program.attribute_location("aPosition")?,
             rect_vertices_buffer: buffer_rect,
             program,
-        }
+        })
+    }
+
+    // Uniform and attribute names that do not match the shaders
+    pub fn shader_warnings(&self) -> Vec<String> {
+        self.program.warnings()
     }
 
-    pub fn render(&self, gl: &WebGlRenderingContext, 
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(&self, gl: &B, 
         bottom: f32,
         top: f32,
         left: f32,
@@ -59,22 +53,20 @@ impl Color2D{
         canvas_height: f32,
         canvas_width: f32
     ) {
-        gl.use_program(Some(&self.program));
-        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.rect_vertices_buffer));
-        // How many values per vertex (would be 3 for 3D)
-        gl.vertex_attrib_pointer_with_i32(0, 2, GL::FLOAT, false, 0, 0);
+        self.program.use_program(gl);
         // Set the array to the "position" attribute in the vertex shader
         // Note the difference between "attrib" and "uniform"
-        gl.enable_vertex_attrib_array(0);
+        self.rect_vertices_buffer.bind_attribute(gl, self.a_position, 0);
         
         // Assign values to the variables in the shaders
-        gl.uniform4f(Some(&self.u_color), 
+        // These are all variables that are used in the shaders
+        self.program.set_vec4(gl, "uColor",
             0.0, 
             0.5, 
             0.4, 
             1.0
         );
-        gl.uniform1f(Some(&self.u_opacity), 1.0);
+        self.program.set_float(gl, "uOpacity", 1.0);
 
         let translation_mat = cf::translation_matrix(
             2. * left / canvas_width - 1.,
@@ -89,7 +81,7 @@ impl Color2D{
         );
         // Order of multiplication is important
         let transform_mat = cf::mult_matrix_4(scale_mat, translation_mat);
-        gl.uniform_matrix4fv_with_f32_array(Some(&self.u_transform), false, &transform_mat);
-        gl.draw_arrays(GL::TRIANGLES, 0, (self.rect_vertices_len / 2) as i32);
+        self.program.set_mat4(gl, "uTransform", &transform_mat);
+        gl.draw_arrays(GL::TRIANGLES, 0, self.rect_vertices_buffer.vertex_count() as i32);
     }
 }
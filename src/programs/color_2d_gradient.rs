@@ -1,25 +1,28 @@
-use wasm_bindgen::JsCast;
 use web_sys::WebGlRenderingContext as GL;
-use web_sys::*;
-use js_sys::WebAssembly;
+use crate::backend::buffers::{IndexBuffer, VertexBuffer};
+use crate::backend::program::ShaderProgram;
+use crate::backend::RenderBackend;
 use crate::common_funcs as cf;
+use crate::error::Error;
 
-pub struct Color2DGradient{
-    program: WebGlProgram,
-    index_count: i32,
-    rect_vertices_buffer: WebGlBuffer,
-    color_buffer: WebGlBuffer,
-    
-    u_opacity: WebGlUniformLocation,
-    u_transform: WebGlUniformLocation,
+pub struct Color2DGradient<B: RenderBackend> {
+    program: ShaderProgram<B>,
+    rect_vertices_buffer: VertexBuffer<B>,
+    indices_buffer: IndexBuffer<B>,
+    color_buffer: VertexBuffer<B>,
+    a_position: u32,
+    a_color: u32,
 }
 
-impl Color2DGradient{
-    pub fn new(gl: &WebGlRenderingContext) -> Self {
-        let program = cf::link_program(gl, 
+impl<B: RenderBackend> Color2DGradient<B> {
+    pub fn new(gl: &B) -> Result<Self, Error> {
+        let program = ShaderProgram::new(
+            gl,
             crate::shaders::vertex::color_2d_gradient::SHADER, 
             crate::shaders::fragment::varying_color_from_vertex::SHADER,
-        ).unwrap();
+            &[],
+            &["aPosition", "aColor"],
+        )?;
 
         // Be more efficient by storing just the indices of the vertices
         let vertices_rect: [f32; 8] = [
@@ -29,56 +32,40 @@ impl Color2DGradient{
             1., 0., // x, y
         ];
         // This *must* be marked as u16 or it will not work
-        let indices_rect: [u16; 6] = [
+        let indices_rect = cf::MeshIndices::U16(vec![
             0, 1, 2,
             2, 1, 3,
+        ]);
+        // The color for each vertex (intermediate pixels are interpolated)
+        let colors: [f32; 16] = [
+            1., 0., 0., 1., // red
+            0., 1., 0., 1., // green
+            0., 0., 1., 1., // blue
+            1., 1., 1., 1., // white
         ];
 
-        // Required to store data on the GPU
-        let memory_buffer = wasm_bindgen::memory()
-            .dyn_into::<WebAssembly::Memory>()
-            .unwrap()
-            .buffer();
-        
-        let vertices_location = vertices_rect.as_ptr() as u32 / 4;
-        let vert_array = js_sys::Float32Array::new(&memory_buffer).subarray(
-            vertices_location, 
-            vertices_location + vertices_rect.len() as u32
-        );
-                let buffer_rect = gl.create_buffer().ok_or("failed to create buffer").unwrap();
-        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&buffer_rect));
-        gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &vert_array, GL::STATIC_DRAW);
-
+        // Required to store data on the GPU, two values per vertex (x, y)
+        let buffer_rect = VertexBuffer::with_data(gl, 2, &vertices_rect, GL::STATIC_DRAW)?;
+        let buffer_indices_rect = IndexBuffer::with_data(gl, &indices_rect, GL::STATIC_DRAW)?;
 
-        // Required to store data on the GPU
-        let indices_memory = wasm_bindgen::memory()
-            .dyn_into::<WebAssembly::Memory>()
-            .unwrap()
-            .buffer();
-        let indices_location = indices_rect.as_ptr() as u32 / 2;
-        let indices_array = js_sys::Uint16Array::new(&indices_memory).subarray(
-            indices_location,
-            indices_location + indices_rect.len() as u32
-        );
-        let buffer_indices_rect = gl.create_buffer().ok_or("failed to create buffer").unwrap();
-        // Note how we now say ELEMENT_ARRAY_BUFFER instead of ARRAY_BUFFER
-        // This is how WebGL knows to use the indices
-        gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(&buffer_indices_rect));
-        gl.buffer_data_with_array_buffer_view(GL::ELEMENT_ARRAY_BUFFER, &indices_array, GL::STATIC_DRAW);
-
-        Self {
-            // These are all variables that are used in the shaders
-            u_opacity: gl.get_uniform_location(&program, "uOpacity").unwrap(),
-            u_transform: gl.get_uniform_location(&program, "uTransform").unwrap(),
-            index_count: indices_rect.len() as i32,
-            // Dynamically filled in every render instead of being hardcoded
-            color_buffer: gl.create_buffer().ok_or("failed to create buffer").unwrap(),
+        Ok(Self {
+            a_position: program.attribute_location("aPosition")?,
+            a_color: program.attribute_location("aColor")?,
+            // Four values per vertex (RGBA)
+            color_buffer: VertexBuffer::with_data(gl, 4, &colors, GL::STATIC_DRAW)?,
             rect_vertices_buffer: buffer_rect,
+            indices_buffer: buffer_indices_rect,
             program,
-        }
+        })
     }
 
-    pub fn render(&self, gl: &WebGlRenderingContext, 
+    // Uniform and attribute names that do not match the shaders
+    pub fn shader_warnings(&self) -> Vec<String> {
+        self.program.warnings()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(&self, gl: &B, 
         bottom: f32,
         top: f32,
         left: f32,
@@ -86,40 +73,13 @@ impl Color2DGradient{
         canvas_height: f32,
         canvas_width: f32
     ) {
-        gl.use_program(Some(&self.program));
-        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.rect_vertices_buffer));
+        self.program.use_program(gl);
         // Set the array to the "position" attribute in the vertex shader
         // Note the difference between "attrib" and "uniform"
-        gl.enable_vertex_attrib_array(0);
-        // How many values per vertex (would be 3 for 3D)
-        gl.vertex_attrib_pointer_with_i32(0, 2, GL::FLOAT, false, 0, 0);
-        
-        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.color_buffer));
-        // Attribute position 1
-        gl.enable_vertex_attrib_array(1);
-        // Four values per vertex (RGBA)
-        gl.vertex_attrib_pointer_with_i32(1, 4, GL::FLOAT, false, 0, 0);
-        
-        // The color for each vertex (intermediate pixels are interpolated)
-        let colors: [f32; 16] = [
-            1., 0., 0., 1., // red
-            0., 1., 0., 1., // green
-            0., 0., 1., 1., // blue
-            1., 1., 1., 1., // white
-        ];
-        // Pass the color data to the GPU
-        let colors_memory_buffer = wasm_bindgen::memory()
-            .dyn_into::<WebAssembly::Memory>()
-            .unwrap()
-            .buffer();
-        let colors_location = colors.as_ptr() as u32 / 4;
-        let colors_array = js_sys::Float32Array::new(&colors_memory_buffer).subarray(
-            colors_location,
-            colors_location + colors.len() as u32
-        );
-        gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &colors_array, GL::DYNAMIC_DRAW);
+        self.rect_vertices_buffer.bind_attribute(gl, self.a_position, 0);
+        self.color_buffer.bind_attribute(gl, self.a_color, 0);
         
-        gl.uniform1f(Some(&self.u_opacity), 1.0);
+        self.program.set_float(gl, "uOpacity", 1.0);
 
         let translation_mat = cf::translation_matrix(
             2. * left / canvas_width - 1.,
@@ -134,7 +94,7 @@ impl Color2DGradient{
         );
         // Order of multiplication is important
         let transform_mat = cf::mult_matrix_4(scale_mat, translation_mat);
-        gl.uniform_matrix4fv_with_f32_array(Some(&self.u_transform), false, &transform_mat);
-        gl.draw_elements_with_i32(GL::TRIANGLES, self.index_count, GL::UNSIGNED_SHORT, 0);
+        self.program.set_mat4(gl, "uTransform", &transform_mat);
+        self.indices_buffer.draw(gl, GL::TRIANGLES, self.indices_buffer.len());
     }
 }
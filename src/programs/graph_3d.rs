@@ -1,83 +1,420 @@
-use wasm_bindgen::JsCast;
+use std::cell::RefCell;
+use nalgebra::UnitQuaternion;
 use web_sys::WebGlRenderingContext as GL;
-use web_sys::*;
-use js_sys::WebAssembly;
+use crate::backend::buffers::{IndexBuffer, VertexBuffer};
+use crate::backend::program::ShaderProgram;
+use crate::backend::RenderBackend;
 use crate::common_funcs as cf;
 use crate::constants as c;
+use crate::error::Error;
+use crate::plot_domain::{AxisRange, PlotDomain};
 
-pub struct Graph3D {
-    program: WebGlProgram,
-    indices_buffer: WebGlBuffer,
-    index_count: i32,
-    position_buffer: WebGlBuffer,
-    y_buffer: WebGlBuffer,
-    normals_buffer: WebGlBuffer,
-    u_opacity: WebGlUniformLocation,
-    u_projection : WebGlUniformLocation,
-    u_normals_rotation: WebGlUniformLocation,
-}
-
-impl Graph3D {
-    pub fn new(gl: &WebGlRenderingContext) -> Self {
-        let program = cf::link_program(gl, 
-            crate::shaders::vertex::graph_3d::SHADER, 
-            crate::shaders::fragment::varying_color_from_vertex::SHADER,
-        ).unwrap();
-
-        let (positions, indices) = cf::get_position_grid_n_by_n(c::GRID_SIZE);
-
-        let memory_buffer = wasm_bindgen::memory()
-            .dyn_into::<WebAssembly::Memory>()
-            .unwrap()
-            .buffer();
-        let vertices_location = positions.as_ptr() as u32 / 4;
-        let vert_array = js_sys::Float32Array::new(&memory_buffer)
-            .subarray(vertices_location, vertices_location + positions.len() as u32);
-        let position_buffer = gl.create_buffer().ok_or("failed to create buffer").unwrap();
-        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&position_buffer));
-        gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &vert_array, GL::STATIC_DRAW);
-
-        let indices_memory_buffer = wasm_bindgen::memory()
-            .dyn_into::<WebAssembly::Memory>()
-            .unwrap()
-            .buffer();
-        let indices_location = indices.as_ptr() as u32 / 2;
-        let indices_array = js_sys::Uint16Array::new(&indices_memory_buffer).subarray(
-            indices_location,
-            indices_location + indices.len() as u32
-        );
-        let indices_buffer = gl.create_buffer().ok_or("failed to create buffer").unwrap();
-        gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(&indices_buffer));
-        gl.buffer_data_with_array_buffer_view(GL::ELEMENT_ARRAY_BUFFER, &indices_array, GL::STATIC_DRAW);
+// Surfaces that shaders::vertex::graph_3d_surface evaluates on the GPU. The shader cannot
+// fit the y range to the heights, so an automatic one is taken from the bounds of the function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinSurface {
+    Ripple,
+    Waves,
+    Saddle,
+}
+
+impl BuiltinSurface {
+    // The same function as an Expression, for the CPU path
+    pub fn expression(&self) -> &'static str {
+        match self {
+            BuiltinSurface::Ripple => c::DEFAULT_SURFACE_EXPRESSION,
+            BuiltinSurface::Waves => "0.2 * sin(x + t * 0.001) * cos(z)",
+            BuiltinSurface::Saddle => "0.02 * (x^2 - z^2)",
+        }
+    }
+
+    // The y range to draw it with over `domain`: the domain's own, or the bounds of the function
+    // over the x and z ranges when it is automatic. None when those bounds overflow
+    pub fn y_range(&self, domain: &PlotDomain) -> Option<AxisRange> {
+        if let Some(y_range) = domain.y {
+            return Some(y_range);
+        }
+        match self {
+            BuiltinSurface::Ripple => AxisRange::new(-0.15, 0.15).ok(),
+            BuiltinSurface::Waves => AxisRange::new(-0.2, 0.2).ok(),
+            BuiltinSurface::Saddle => {
+                let (x_min, x_max) = square_bounds(domain.x);
+                let (z_min, z_max) = square_bounds(domain.z);
+                AxisRange::new(0.02 * (x_min - z_max), 0.02 * (x_max - z_min)).ok()
+            }
+        }
+    }
+
+    // Value of uSurface that selects it in the shader
+    pub(crate) fn shader_index(&self) -> f32 {
+        match self {
+            BuiltinSurface::Ripple => 0.,
+            BuiltinSurface::Waves => 1.,
+            BuiltinSurface::Saddle => 2.,
+        }
+    }
+}
+
+// Smallest and largest square of a value in `range`
+fn square_bounds(range: AxisRange) -> (f32, f32) {
+    let (min, max) = (range.min * range.min, range.max * range.max);
+    if range.min <= 0. && range.max >= 0. {
+        (0., min.max(max))
+    } else {
+        (min.min(max), min.max(max))
+    }
+}
+
+impl std::str::FromStr for BuiltinSurface {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ripple" => Ok(BuiltinSurface::Ripple),
+            "waves" => Ok(BuiltinSurface::Waves),
+            "saddle" => Ok(BuiltinSurface::Saddle),
+            _ => Err(format!("Unknown built-in surface '{}', expected one of ripple, waves, saddle", s)),
+        }
+    }
+}
+
+// How the light is computed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LightingModel {
+    // Diffuse light only, computed per vertex
+    #[default]
+    Lambert,
+    // Per pixel, with specular highlights around the reflection of the light
+    Phong,
+    // Per pixel, with the cheaper halfway vector highlights. They are wider than Phong's
+    // for the same shininess
+    BlinnPhong,
+}
+
+impl std::str::FromStr for LightingModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lambert" => Ok(LightingModel::Lambert),
+            "phong" => Ok(LightingModel::Phong),
+            "blinn-phong" => Ok(LightingModel::BlinnPhong),
+            _ => Err(format!("Unknown lighting model '{}', expected one of lambert, phong, blinn-phong", s)),
+        }
+    }
+}
+
+// The directional light, set as uniforms every frame. It stays fixed relative to the
+// viewer while the graph rotates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    // Towards the light: x right, y up, z towards the viewer
+    pub direction: [f32; 3],
+    pub color: [f32; 3],
+    // Color of the ambient light, the red of the original shader by default
+    pub ambient_color: [f32; 3],
+    // Fraction of `ambient_color`, and of `color` as diffuse and specular light
+    pub ambient: f32,
+    pub diffuse: f32,
+    pub specular: f32,
+    // Specular exponent, higher for smaller and sharper highlights
+    pub shininess: f32,
+}
+
+impl Default for Light {
+    fn default() -> Self {
         Self {
-            // "Uniform" is uniform across both vertex and fragment shaders
-            u_opacity: gl.get_uniform_location(&program, "uOpacity").unwrap(),
-            u_projection: gl.get_uniform_location(&program, "uProjection").unwrap(),
-            u_normals_rotation: gl.get_uniform_location(&program, "uNormalsRotation").unwrap(),
+            direction: [-0.85, 0.8, 0.75],
+            color: [1., 1., 1.],
+            ambient_color: [1., 0., 0.],
+            ambient: 0.1,
+            diffuse: 1.,
+            specular: 0.5,
+            shininess: 32.,
+        }
+    }
+}
+
+impl Light {
+    fn validate(&self) -> Result<(), String> {
+        let [x, y, z] = self.direction;
+        if !(x * x + y * y + z * z).is_normal() {
+            return Err(String::from("The light direction must be a non-zero vector"));
+        }
+        if !self.color.iter().all(|channel| channel.is_finite() && *channel >= 0.) {
+            return Err(String::from("The light color must be non-negative numbers"));
+        }
+        if !self.ambient_color.iter().all(|channel| channel.is_finite() && *channel >= 0.) {
+            return Err(String::from("The ambient color must be non-negative numbers"));
+        }
+        if ![self.ambient, self.diffuse, self.specular].iter().all(|k| k.is_finite() && *k >= 0.) {
+            return Err(String::from("The ambient, diffuse and specular coefficients must be non-negative numbers"));
+        }
+        if !(self.shininess.is_finite() && self.shininess >= 1.) {
+            return Err(String::from("The shininess must be a number of at least 1"));
+        }
+        Ok(())
+    }
+}
+
+// Optional shading of the surface, compiled into the shaders as #defines
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SurfaceStyle {
+    // Colors the surface by height instead of a single color
+    pub colormap: bool,
+    // Fades the far side of the surface toward this color, usually the background
+    pub fog: Option<[f32; 3]>,
+    pub lighting: LightingModel,
+}
+
+impl SurfaceStyle {
+    fn defines(&self) -> Vec<&'static str> {
+        let mut return_var = Vec::new();
+        if self.colormap {
+            return_var.push("COLORMAP");
+        }
+        if self.fog.is_some() {
+            return_var.push("FOG");
+        }
+        match self.lighting {
+            LightingModel::Lambert => {}
+            LightingModel::Phong => return_var.push("PER_PIXEL_LIGHTING"),
+            LightingModel::BlinnPhong => return_var.extend(["PER_PIXEL_LIGHTING", "BLINN_PHONG"]),
+        }
+        return_var
+    }
+
+    fn fragment_shader(&self) -> &'static str {
+        match self.lighting {
+            LightingModel::Lambert => crate::shaders::fragment::varying_color_from_vertex::SHADER,
+            LightingModel::Phong | LightingModel::BlinnPhong => crate::shaders::fragment::lit_surface::SHADER,
+        }
+    }
+}
+
+// shaders::vertex::graph_3d_surface
+struct SurfaceProgram<B: RenderBackend> {
+    program: ShaderProgram<B>,
+    a_position: u32,
+}
+
+impl<B: RenderBackend> SurfaceProgram<B> {
+    fn new(gl: &B, style: SurfaceStyle) -> Result<Self, Error> {
+        let program = ShaderProgram::new(
+            gl,
+            crate::shaders::vertex::graph_3d_surface::SHADER,
+            style.fragment_shader(),
+            &style.defines(),
+            &["aPosition"],
+        )?;
+        Ok(Self {
+            a_position: program.attribute_location("aPosition")?,
+            program,
+        })
+    }
+}
+
+// The height and normal buffers, written by set_y_values and drawn by every render
+// until the heights change. `normals` is kept so that the upload does not allocate
+struct UploadedHeights<B: RenderBackend> {
+    y_buffer: VertexBuffer<B>,
+    normals_buffer: VertexBuffer<B>,
+    normals: Vec<f32>,
+    // False until the first upload and after the grid or the normal mode changes
+    valid: bool,
+}
+
+pub struct Graph3D<B: RenderBackend> {
+    program: ShaderProgram<B>,
+    surface_program: SurfaceProgram<B>,
+    indices_buffer: IndexBuffer<B>,
+    // Rows of grid cells per draw call, less than the grid size when the mesh is too
+    // big for u16 indices and 32-bit indices are not available
+    rows_per_chunk: usize,
+    grid_size: cf::GridSize,
+    normal_mode: cf::NormalMode,
+    style: SurfaceStyle,
+    light: Light,
+    position_buffer: VertexBuffer<B>,
+    uploaded_heights: RefCell<UploadedHeights<B>>,
+    a_position: u32,
+    a_y: u32,
+    a_vertex_normal: u32,
+}
+
+impl<B: RenderBackend> Graph3D<B> {
+    pub fn new(gl: &B) -> Result<Self, Error> {
+        let grid_size = cf::GridSize::square(c::DEFAULT_GRID_SIZE).map_err(Error::Mesh)?;
+        Self::with_grid_size(gl, grid_size)
+    }
+
+    pub fn with_grid_size(gl: &B, grid_size: cf::GridSize) -> Result<Self, Error> {
+        let style = SurfaceStyle::default();
+        let program = Self::link_program(gl, style)?;
+
+        // x, y, z per vertex
+        let mut position_buffer = VertexBuffer::new(gl, 3, GL::STATIC_DRAW)?;
+        let mut indices_buffer = IndexBuffer::new(gl, GL::STATIC_DRAW)?;
+        let rows_per_chunk = Self::upload_grid(gl, &mut position_buffer, &mut indices_buffer, grid_size)?;
+        Ok(Self {
+            a_position: program.attribute_location("aPosition")?,
+            a_y: program.attribute_location("aY")?,
+            a_vertex_normal: program.attribute_location("aVertexNormal")?,
             // Define program last as it gets borrowed by the above functions
             program,
+            surface_program: SurfaceProgram::new(gl, style)?,
             position_buffer,
             indices_buffer,
-            index_count: indices_array.length() as i32, 
-            y_buffer: gl.create_buffer().ok_or("failed to create buffer").unwrap(),
-            normals_buffer: gl.create_buffer().ok_or("failed to create buffer").unwrap(),
-            
+            rows_per_chunk,
+            grid_size,
+            normal_mode: cf::NormalMode::default(),
+            style,
+            light: Light::default(),
+            uploaded_heights: RefCell::new(UploadedHeights {
+                y_buffer: VertexBuffer::new(gl, 1, GL::DYNAMIC_DRAW)?,
+                normals_buffer: VertexBuffer::new(gl, 3, GL::DYNAMIC_DRAW)?,
+                normals: Vec::new(),
+                valid: false,
+            }),
+        })
+    }
+
+    // shaders::vertex::graph_3d, the attributes get the same locations whatever the style
+    fn link_program(gl: &B, style: SurfaceStyle) -> Result<ShaderProgram<B>, Error> {
+        ShaderProgram::new(
+            gl,
+            crate::shaders::vertex::graph_3d::SHADER,
+            style.fragment_shader(),
+            &style.defines(),
+            &["aPosition", "aY", "aVertexNormal"],
+        )
+    }
+
+    // Uniform and attribute names that do not match the shaders, of both programs
+    pub fn shader_warnings(&self) -> Vec<String> {
+        let mut return_var = self.program.warnings();
+        return_var.extend(self.surface_program.program.warnings());
+        return_var
+    }
+
+    pub fn grid_size(&self) -> cf::GridSize {
+        self.grid_size
+    }
+
+    pub fn normal_mode(&self) -> cf::NormalMode {
+        self.normal_mode
+    }
+
+    // The heights must then be set again, the normals are computed from them
+    pub fn set_normal_mode(&mut self, normal_mode: cf::NormalMode) {
+        if normal_mode != self.normal_mode {
+            self.normal_mode = normal_mode;
+            self.uploaded_heights.borrow_mut().valid = false;
+        }
+    }
+
+    pub fn style(&self) -> SurfaceStyle {
+        self.style
+    }
+
+    // Relinks both programs when the defines change. Keeps the previous style on error
+    pub fn set_style(&mut self, gl: &B, style: SurfaceStyle) -> Result<(), Error> {
+        if style.defines() != self.style.defines() {
+            let program = Self::link_program(gl, style)?;
+            self.surface_program = SurfaceProgram::new(gl, style)?;
+            self.program = program;
+        }
+        self.style = style;
+        Ok(())
+    }
+
+    pub fn light(&self) -> Light {
+        self.light
+    }
+
+    pub fn set_light(&mut self, light: Light) -> Result<(), Error> {
+        light.validate().map_err(Error::InvalidOption)?;
+        self.light = light;
+        Ok(())
+    }
+
+    // Light and fog uniforms, only the ones the shaders use with the current style
+    fn set_shading_uniforms(&self, gl: &B, program: &ShaderProgram<B>) {
+        let light = &self.light;
+        let [x, y, z] = light.direction;
+        program.set_vec3(gl, "uLightDirection", x, y, z);
+        let [red, green, blue] = light.color;
+        program.set_vec3(gl, "uLightColor", red, green, blue);
+        let [red, green, blue] = light.ambient_color.map(|channel| channel * light.ambient);
+        program.set_vec3(gl, "uAmbientLight", red, green, blue);
+        program.set_float(gl, "uDiffuse", light.diffuse);
+        if self.style.lighting != LightingModel::Lambert {
+            program.set_float(gl, "uSpecular", light.specular);
+            program.set_float(gl, "uShininess", light.shininess);
+        }
+        if let Some([red, green, blue]) = self.style.fog {
+            program.set_vec3(gl, "uFogColor", red, green, blue);
+        }
+    }
+
+    // Regenerates the vertex positions and indices, the heights must then be set
+    // again for the new grid size
+    pub fn set_grid_size(&mut self, gl: &B, grid_size: cf::GridSize) -> Result<(), Error> {
+        if grid_size == self.grid_size {
+            return Ok(());
         }
+        let rows_per_chunk = Self::upload_grid(gl, &mut self.position_buffer, &mut self.indices_buffer, grid_size)?;
+        self.rows_per_chunk = rows_per_chunk;
+        self.grid_size = grid_size;
+        self.uploaded_heights.borrow_mut().valid = false;
+        Ok(())
     }
+
+    // Returns the number of cell rows per draw call. Leaves the buffers untouched on error
+    fn upload_grid(gl: &B, position_buffer: &mut VertexBuffer<B>, indices_buffer: &mut IndexBuffer<B>, grid_size: cf::GridSize) -> Result<usize, Error> {
+        let (indices, rows_per_chunk) = match cf::get_grid_indices(grid_size) {
+            cf::MeshIndices::U32(_) if !gl.enable_element_index_uint() => {
+                // Draw a few rows at a time, with u16 indices relative to the first vertex of
+                // the chunk. The attribute offsets move to that vertex for every draw call
+                let rows_per_chunk = ((u16::MAX as usize + 1) / (grid_size.x() + 1)).saturating_sub(1);
+                if rows_per_chunk == 0 {
+                    return Err(Error::Mesh(format!(
+                        "Grid size {} x {} needs 32-bit indices (OES_element_index_uint), which are not supported here",
+                        grid_size.x(), grid_size.z()
+                    )));
+                }
+                let chunk = cf::GridSize::new(grid_size.x(), rows_per_chunk).map_err(Error::Mesh)?;
+                (cf::get_grid_indices(chunk), rows_per_chunk)
+            }
+            indices => (indices, grid_size.z()),
+        };
+
+        position_buffer.upload(gl, &cf::get_grid_positions(grid_size));
+        indices_buffer.upload(gl, &indices);
+        Ok(rows_per_chunk)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn render(
         &self,
-        gl: &WebGlRenderingContext,
+        gl: &B,
         bottom: f32,
         top: f32,
         left: f32,
         right: f32,
         canvas_height: f32,
         canvas_width: f32,
-        rotation_angle_x_axis: f32,
-        rotation_angle_y_axis: f32,
-        y_vals: &[f32],
-    ) {
-        gl.use_program(Some(&self.program));
+        rotation: &UnitQuaternion<f32>,
+        zoom: f32,
+        pan_x: f32,
+        pan_y: f32,
+        orthographic_blend: f32,
+    ) -> Result<(), Error> {
+        let uploaded = self.uploaded_heights.borrow();
+        if !uploaded.valid {
+            return Err(Error::Mesh(String::from("No heights for the current grid and normal mode, call set_y_values first")));
+        }
+        self.program.use_program(gl);
         let matrices = cf::get_3d_matrices(
             bottom,
             top,
@@ -85,49 +422,114 @@ impl Graph3D {
             right,
             canvas_height,
             canvas_width,
-            rotation_angle_x_axis,
-            rotation_angle_y_axis,
+            rotation,
+            zoom,
+            pan_x,
+            pan_y,
+            orthographic_blend,
         );
-        gl.uniform_matrix4fv_with_f32_array(Some(&self.u_projection), false, &matrices.projection);
-        gl.uniform_matrix4fv_with_f32_array(Some(&self.u_normals_rotation), false, &matrices.normals_rotation);
-        gl.uniform1f(Some(&self.u_opacity), 1.);
-        
-        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.position_buffer));
-        gl.vertex_attrib_pointer_with_i32(0, 3, GL::FLOAT, false, 0, 0);
-        gl.enable_vertex_attrib_array(0);
-
-        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.y_buffer));
-        gl.vertex_attrib_pointer_with_i32(1, 1, GL::FLOAT, false, 0, 0);
-        gl.enable_vertex_attrib_array(1);
-
-        let y_memory_buffer = wasm_bindgen::memory()
-            .dyn_into::<WebAssembly::Memory>()
-            .unwrap()
-            .buffer();
-        let y_location = y_vals.as_ptr() as u32 / 4;
-        let y_array = js_sys::Float32Array::new(&y_memory_buffer).subarray(
-            y_location,
-            y_location + y_vals.len() as u32
+        // "Uniform" is uniform across both vertex and fragment shaders
+        self.program.set_mat4(gl, "uProjection", &matrices.projection);
+        self.program.set_mat4(gl, "uNormalsRotation", &matrices.normals_rotation);
+        self.program.set_float(gl, "uOpacity", 1.);
+        self.set_shading_uniforms(gl, &self.program);
+
+        self.draw_grid(gl, &[
+            (self.a_position, &self.position_buffer),
+            (self.a_y, &uploaded.y_buffer),
+            (self.a_vertex_normal, &uploaded.normals_buffer),
+        ]);
+        Ok(())
+    }
+
+    // Uploads the heights drawn by render, one per grid vertex, and their normals.
+    // Only needs calling when they change
+    pub fn set_y_values(&self, gl: &B, y_vals: &[f32]) -> Result<(), Error> {
+        if y_vals.len() != self.grid_size.vertex_count() {
+            return Err(Error::Mesh(format!(
+                "Expected {} heights for a {} x {} grid, got {}",
+                self.grid_size.vertex_count(), self.grid_size.x(), self.grid_size.z(), y_vals.len()
+            )));
+        }
+        let mut uploaded = self.uploaded_heights.borrow_mut();
+        let uploaded = &mut *uploaded;
+        cf::update_grid_normals(self.grid_size, y_vals, self.normal_mode, &mut uploaded.normals);
+        uploaded.y_buffer.upload(gl, y_vals);
+        uploaded.normals_buffer.upload(gl, &uploaded.normals);
+        uploaded.valid = true;
+        Ok(())
+    }
+
+    // Draws one of the built-in surfaces with the heights and normals computed in the
+    // vertex shader, so nothing is uploaded per frame. Normals are always central
+    // differences, whatever the normal mode. Heights are mapped with `y_range`, the
+    // GPU cannot fit it to the surface
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_builtin(
+        &self,
+        gl: &B,
+        bottom: f32,
+        top: f32,
+        left: f32,
+        right: f32,
+        canvas_height: f32,
+        canvas_width: f32,
+        rotation: &UnitQuaternion<f32>,
+        zoom: f32,
+        pan_x: f32,
+        pan_y: f32,
+        orthographic_blend: f32,
+        surface: BuiltinSurface,
+        time: f32,
+        domain: &PlotDomain,
+        y_range: AxisRange,
+    ) {
+        let surface_program = &self.surface_program;
+        let program = &surface_program.program;
+        program.use_program(gl);
+        let matrices = cf::get_3d_matrices(
+            bottom,
+            top,
+            left,
+            right,
+            canvas_height,
+            canvas_width,
+            rotation,
+            zoom,
+            pan_x,
+            pan_y,
+            orthographic_blend,
         );
-        gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &y_array, GL::DYNAMIC_DRAW);
-
-        gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.normals_buffer));
-        gl.vertex_attrib_pointer_with_i32(2, 3, GL::FLOAT, false, 0, 0);
-        gl.enable_vertex_attrib_array(2);
-
-        let normals_vals = cf::get_grid_normals(c::GRID_SIZE, &y_vals);
-        let normals_memory_buffer = wasm_bindgen::memory()
-            .dyn_into::<WebAssembly::Memory>()
-            .unwrap()
-            .buffer();
-        let normals_location = normals_vals.as_ptr() as u32 / 4;
-        let normals_array = js_sys::Float32Array::new(&normals_memory_buffer).subarray(
-            normals_location,
-            normals_location + normals_vals.len() as u32
+        program.set_mat4(gl, "uProjection", &matrices.projection);
+        program.set_mat4(gl, "uNormalsRotation", &matrices.normals_rotation);
+        program.set_float(gl, "uOpacity", 1.);
+        self.set_shading_uniforms(gl, program);
+        program.set_float(gl, "uTime", time);
+        program.set_float(gl, "uSurface", surface.shader_index());
+        program.set_vec4(gl, "uDomain", domain.x.min, domain.x.max, domain.z.min, domain.z.max);
+        program.set_vec2(gl, "uYRange", y_range.min, y_range.max);
+        // The grid spans [-1, 1]
+        program.set_vec2(
+            gl,
+            "uGridStep",
+            2. / self.grid_size.x() as f32,
+            2. / self.grid_size.z() as f32,
         );
-        gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &normals_array, GL::DYNAMIC_DRAW);
 
-        gl.draw_elements_with_i32(GL::TRIANGLES, self.index_count, GL::UNSIGNED_SHORT, 0);
+        self.draw_grid(gl, &[(surface_program.a_position, &self.position_buffer)]);
+    }
+
+    // Draws the triangles of the grid with the attribute buffers given with their locations.
+    // A single draw call unless the mesh is split into chunks
+    fn draw_grid(&self, gl: &B, attributes: &[(u32, &VertexBuffer<B>)]) {
+        for first_row in (0..self.grid_size.z()).step_by(self.rows_per_chunk) {
+            let rows = self.rows_per_chunk.min(self.grid_size.z() - first_row);
+            let first_vertex = first_row * (self.grid_size.x() + 1);
 
+            for (location, buffer) in attributes {
+                buffer.bind_attribute(gl, *location, first_vertex);
+            }
+            self.indices_buffer.draw(gl, GL::TRIANGLES, rows * self.grid_size.x() * 6);
+        }
     }
 }
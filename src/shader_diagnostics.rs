@@ -0,0 +1,135 @@
+// Turns the info log of a failed shader compilation into line / column diagnostics, and
+// prints them under the GLSL lines they point at
+// Understands the ANGLE format used by browsers, "ERROR: 0:12: 'x' : undeclared identifier",
+// and the Mesa one, "0:12(5): error: `x' undeclared"
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // 1-based, counting the blank first line of the shader strings
+    pub line: Option<usize>,
+    // 1-based, when the compiler reports one
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+// One diagnostic per non-empty line of the log
+pub fn parse_info_log(log: &str) -> Vec<Diagnostic> {
+    log.lines().filter_map(parse_log_line).collect()
+}
+
+fn parse_log_line(text: &str) -> Option<Diagnostic> {
+    // Some drivers end the log with a NUL
+    let text = text.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+    if text.is_empty() {
+        return None;
+    }
+    let (severity, rest) = strip_severity(text);
+    let return_var = match parse_location(rest) {
+        Some((line, column, message)) => {
+            // Mesa puts the severity after the location
+            let (message_severity, message) = strip_severity(message);
+            Diagnostic {
+                severity: severity.or(message_severity).unwrap_or(Severity::Error),
+                line: Some(line),
+                column,
+                message: message.to_string(),
+            }
+        }
+        None => Diagnostic {
+            severity: severity.unwrap_or(Severity::Error),
+            line: None,
+            column: None,
+            message: rest.to_string(),
+        },
+    };
+    Some(return_var)
+}
+
+fn strip_severity(text: &str) -> (Option<Severity>, &str) {
+    let prefixes = [
+        ("ERROR:", Severity::Error),
+        ("error:", Severity::Error),
+        ("WARNING:", Severity::Warning),
+        ("warning:", Severity::Warning),
+    ];
+    for (prefix, severity) in prefixes {
+        if let Some(rest) = text.strip_prefix(prefix) {
+            return (Some(severity), rest.trim_start());
+        }
+    }
+    (None, text)
+}
+
+// "<source string>:<line>: message" or "<source string>:<line>(<column>): message"
+fn parse_location(text: &str) -> Option<(usize, Option<usize>, &str)> {
+    let (source_string, rest) = text.split_once(':')?;
+    if source_string.is_empty() || !source_string.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (location, message) = rest.split_once(':')?;
+    let (line, column) = match location.split_once('(') {
+        Some((line, column)) => (line, Some(column.strip_suffix(')')?.parse().ok()?)),
+        None => (location, None),
+    };
+    Some((line.trim().parse().ok()?, column, message.trim()))
+}
+
+// Each diagnostic followed by the line it points at and the one before, e.g.
+//
+// error: 'vColour' : undeclared identifier
+//   --> line 3
+//    |
+//  2 | void main() {
+//  3 |     gl_FragColor = vColour;
+//    |     ^^^^^^^^^^^^^^^^^^^^^^^
+//
+// The whole line is underlined when the compiler does not report a column
+pub fn annotate(source: &str, diagnostics: &[Diagnostic]) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut return_var = String::new();
+    for (i, diagnostic) in diagnostics.iter().enumerate() {
+        if i > 0 {
+            return_var.push('\n');
+        }
+        return_var.push_str(&format!("{}: {}", diagnostic.severity, diagnostic.message));
+        let Some(line) = diagnostic.line.filter(|line| (1..=lines.len()).contains(line)) else {
+            continue;
+        };
+        let text = lines[line - 1];
+        let gutter = line.to_string().len();
+        match diagnostic.column {
+            Some(column) => return_var.push_str(&format!("\n{:>gutter$}--> line {}, column {}", "", line, column, gutter = gutter + 1)),
+            None => return_var.push_str(&format!("\n{:>gutter$}--> line {}", "", line, gutter = gutter + 1)),
+        }
+        return_var.push_str(&format!("\n{:>gutter$} |", "", gutter = gutter));
+        if line > 1 {
+            return_var.push_str(&format!("\n{:>gutter$} | {}", line - 1, lines[line - 2], gutter = gutter));
+        }
+        return_var.push_str(&format!("\n{:>gutter$} | {}", line, text, gutter = gutter));
+
+        let indent = text.len() - text.trim_start().len();
+        let (start, width) = match diagnostic.column {
+            Some(column) => (column.saturating_sub(1).min(text.len()), 1),
+            None => (indent, text.trim().len().max(1)),
+        };
+        return_var.push_str(&format!("\n{:>gutter$} | {}{}", "", " ".repeat(start), "^".repeat(width), gutter = gutter));
+    }
+    return_var
+}
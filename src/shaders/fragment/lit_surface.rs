@@ -0,0 +1,23 @@
+// Per-pixel lighting of the 3D graph, for the vertex shaders compiled with PER_PIXEL_LIGHTING
+pub const SHADER: &str = r#"
+    precision mediump float;
+
+    uniform float uOpacity;
+
+    varying vec3 vNormal;
+    varying vec3 vBaseColor;
+    varying float vFogDepth;
+
+    #include "lighting"
+    #include "fog"
+
+    void main() {
+        // Interpolation shortens the normals
+        vec3 normal = normalize(vNormal);
+        vec3 color = vBaseColor * lighting(normal) + specularLighting(normal);
+    #ifdef FOG
+        color = applyFog(color, vFogDepth);
+    #endif
+        gl_FragColor = vec4(color, uOpacity);
+    }
+"#;
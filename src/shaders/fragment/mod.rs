@@ -1,2 +1,3 @@
 pub mod color_2d;
+pub mod lit_surface;
 pub mod varying_color_from_vertex;
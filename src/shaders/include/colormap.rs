@@ -0,0 +1,14 @@
+// Polynomial fit of the viridis colormap, from dark blue at 0 to yellow at 1
+pub const SHADER: &str = r#"
+    vec3 colormap(float t) {
+        float x = clamp(t, 0.0, 1.0);
+        vec3 c0 = vec3(0.2777, 0.0054, 0.3341);
+        vec3 c1 = vec3(0.1051, 1.4046, 1.3846);
+        vec3 c2 = vec3(-0.3309, 0.2148, 0.0951);
+        vec3 c3 = vec3(-4.6342, -5.7991, -19.3324);
+        vec3 c4 = vec3(6.2283, 14.1799, 56.6906);
+        vec3 c5 = vec3(4.7764, -13.7451, -65.3530);
+        vec3 c6 = vec3(-5.4355, 4.6459, 26.3124);
+        return c0 + x * (c1 + x * (c2 + x * (c3 + x * (c4 + x * (c5 + x * c6)))));
+    }
+"#;
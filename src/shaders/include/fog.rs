@@ -0,0 +1,10 @@
+// Fades colors toward uFogColor on the far side of the graph. `depth` is how far behind the
+// center of the graph a point is, in display units (the graph spans [-1, 1])
+pub const SHADER: &str = r#"
+    uniform mediump vec3 uFogColor;
+
+    vec3 applyFog(vec3 color, float depth) {
+        float amount = 0.85 * smoothstep(-0.5, 1.25, depth);
+        return mix(color, uFogColor, amount);
+    }
+"#;
@@ -0,0 +1,33 @@
+// One directional light, fixed relative to the viewer, for normals already rotated with the
+// graph. The uniforms are mediump so they match between the vertex and fragment shaders
+pub const SHADER: &str = r#"
+    // Towards the light: x right, y up, z towards the viewer
+    uniform mediump vec3 uLightDirection;
+    uniform mediump vec3 uLightColor;
+    // Ambient color times the ambient coefficient, independent of uLightColor
+    uniform mediump vec3 uAmbientLight;
+    uniform mediump float uDiffuse;
+    uniform mediump float uSpecular;
+    uniform mediump float uShininess;
+
+    // Ambient and diffuse light, to multiply with the color of the surface
+    vec3 lighting(vec3 normal) {
+        float directional = max(dot(normal, normalize(uLightDirection)), 0.0);
+        return uAmbientLight + uLightColor * (uDiffuse * directional);
+    }
+
+    // Highlight added on top of the lit color. The viewer is far away along +z
+    vec3 specularLighting(vec3 normal) {
+        vec3 lightDirection = normalize(uLightDirection);
+        vec3 viewDirection = vec3(0.0, 0.0, 1.0);
+        if (dot(normal, lightDirection) <= 0.0) {
+            return vec3(0.0);
+        }
+    #ifdef BLINN_PHONG
+        float specular = max(dot(normal, normalize(lightDirection + viewDirection)), 0.0);
+    #else
+        float specular = max(dot(reflect(-lightDirection, normal), viewDirection), 0.0);
+    #endif
+        return uLightColor * uSpecular * pow(specular, uShininess);
+    }
+"#;
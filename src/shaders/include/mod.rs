@@ -0,0 +1,13 @@
+// Snippets the shaders pull in with `#include "<name>"`, see shaders::preprocessor
+pub mod colormap;
+pub mod fog;
+pub mod lighting;
+
+pub fn lookup(name: &str) -> Option<&'static str> {
+    match name {
+        "colormap" => Some(colormap::SHADER),
+        "fog" => Some(fog::SHADER),
+        "lighting" => Some(lighting::SHADER),
+        _ => None,
+    }
+}
@@ -1,2 +1,4 @@
 pub mod fragment;
+pub mod include;
+pub mod preprocessor;
 pub mod vertex;
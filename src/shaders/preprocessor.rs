@@ -0,0 +1,138 @@
+// Composes shader sources before they are compiled: `#include "<name>"` lines are replaced
+// with the snippets in shaders::include, and `#define`s are added at the top so the GLSL
+// preprocessor can toggle features with #ifdef.
+// #ifdef, #ifndef, #else and #endif are evaluated against the defines and the #define and
+// #undef lines above them, so that only includes in the active branches are expanded. A
+// snippet included more than once there is only expanded the first time. The conditionals
+// are kept in the output for the GLSL preprocessor, which picks the same branches. Names
+// it defines itself, like GL_ES, count as undefined here, and #if and #elif are not supported
+use crate::error::Error;
+use super::include;
+
+// An #ifdef or #ifndef waiting for its #endif
+struct Conditional {
+    // Whether the code around the #ifdef is active
+    enclosing_active: bool,
+    condition: bool,
+    in_else: bool,
+}
+
+#[derive(Default)]
+struct State {
+    defined: Vec<String>,
+    // The snippets expanded so far, including the ones being expanded, so snippets that
+    // include each other do not recurse forever
+    included: Vec<String>,
+    conditionals: Vec<Conditional>,
+}
+
+impl State {
+    fn active(&self) -> bool {
+        self.conditionals
+            .last()
+            .is_none_or(|conditional| conditional.enclosing_active && conditional.condition != conditional.in_else)
+    }
+}
+
+// `defines` are the text following "#define ", e.g. "FOG" or "STEPS 4"
+pub fn preprocess(source: &str, defines: &[&str]) -> Result<String, Error> {
+    let mut return_var = String::new();
+    let mut lines = source.lines().peekable();
+    // #version must come before anything but comments and whitespace
+    while let Some(line) = lines.next_if(|line| line.trim().is_empty() || line.trim_start().starts_with("#version")) {
+        return_var.push_str(line);
+        return_var.push('\n');
+    }
+    let mut state = State::default();
+    for define in defines {
+        let name = define.split_whitespace().next().unwrap_or("");
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(Error::Preprocess(format!("Invalid define '{}'", define)));
+        }
+        return_var.push_str(&format!("#define {}\n", define));
+        state.defined.push(name.to_string());
+    }
+
+    for line in lines {
+        expand_line(line, &mut state, &mut return_var)?;
+    }
+    if !state.conditionals.is_empty() {
+        return Err(Error::Preprocess(String::from("#ifdef without #endif")));
+    }
+    Ok(return_var)
+}
+
+fn expand_line(line: &str, state: &mut State, output: &mut String) -> Result<(), Error> {
+    let trimmed = line.trim();
+    if let Some(directive) = trimmed.strip_prefix('#') {
+        update_conditionals(directive, state)
+            .map_err(|message| Error::Preprocess(format!("{} in '{}'", message, trimmed)))?;
+    }
+    let argument = match trimmed.strip_prefix("#include") {
+        Some(argument) if state.active() => argument,
+        // Dropped, the GLSL compiler does not know #include
+        Some(_) => return Ok(()),
+        None => {
+            output.push_str(line);
+            output.push('\n');
+            return Ok(());
+        }
+    };
+    let name = include_name(argument.trim())
+        .ok_or_else(|| Error::Preprocess(format!("Expected #include \"<name>\", got '{}'", trimmed)))?;
+    if state.included.iter().any(|included| included == name) {
+        return Ok(());
+    }
+    let snippet = include::lookup(name)
+        .ok_or_else(|| Error::Preprocess(format!("Unknown shader include \"{}\"", name)))?;
+    state.included.push(name.to_string());
+    let open_conditionals = state.conditionals.len();
+    for line in snippet.lines() {
+        expand_line(line, state, output)?;
+    }
+    if state.conditionals.len() != open_conditionals {
+        return Err(Error::Preprocess(format!("Unbalanced #ifdef and #endif in shader include \"{}\"", name)));
+    }
+    Ok(())
+}
+
+// Tracks the conditionals and defines, `directive` is the line after the '#'
+fn update_conditionals(directive: &str, state: &mut State) -> Result<(), &'static str> {
+    let mut words = directive.split_whitespace();
+    let keyword = words.next().unwrap_or("");
+    let name = words.next();
+    match keyword {
+        "ifdef" | "ifndef" => {
+            let name = name.ok_or("Missing name")?;
+            let defined = state.defined.iter().any(|defined| defined == name);
+            state.conditionals.push(Conditional {
+                enclosing_active: state.active(),
+                condition: defined == (keyword == "ifdef"),
+                in_else: false,
+            });
+        }
+        "else" => {
+            let conditional = state.conditionals.last_mut().ok_or("#else without #ifdef")?;
+            if conditional.in_else {
+                return Err("Second #else");
+            }
+            conditional.in_else = true;
+        }
+        "endif" => {
+            state.conditionals.pop().ok_or("#endif without #ifdef")?;
+        }
+        "if" | "elif" => return Err("Only #ifdef and #ifndef are supported"),
+        "define" if state.active() => state.defined.push(name.ok_or("Missing name")?.to_string()),
+        "undef" if state.active() => state.defined.retain(|defined| Some(defined.as_str()) != name),
+        _ => {}
+    }
+    Ok(())
+}
+
+fn include_name(argument: &str) -> Option<&str> {
+    argument
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .or_else(|| argument.strip_prefix('<').and_then(|rest| rest.strip_suffix('>')))
+        .filter(|name| !name.is_empty())
+}
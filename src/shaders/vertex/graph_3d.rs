@@ -5,21 +5,43 @@ pub const SHADER: &str = r#"
 
     uniform mat4 uProjection;
     uniform mat4 uNormalsRotation;
+    #ifdef PER_PIXEL_LIGHTING
+    // Lit by fragment::lit_surface
+    varying vec3 vNormal;
+    varying vec3 vBaseColor;
+    varying float vFogDepth;
+    #else
     varying lowp vec4 vColor;
-    
+    #endif
+
+    #include "lighting"
+    #include "colormap"
+    #include "fog"
+
     void main() {
         // 4-dimenional helps with projection
         gl_Position = uProjection * vec4(aPosition.x, aY, aPosition.z, 1.0);
         
-        vec3 ambientLight = vec3(0.1, 0.0, 0.0);
-        vec3 directionalLightColor = vec3(1.0, 1.0, 1.0);
-        vec3 directionalVector = normalize(vec3(-0.85, 0.8, 0.75));
-
-        vec4 transformedNormal = uNormalsRotation * vec4(aVertexNormal, 1.0);
-        float directional = max(dot(transformedNormal.xyz, directionalVector), 0.0);
-        vec3 vLighting = ambientLight + (directionalLightColor * directional);
+        vec3 transformedNormal = (uNormalsRotation * vec4(aVertexNormal, 1.0)).xyz;
+    #ifdef COLORMAP
+        // Heights are in [-1, 1] in display coordinates
+        vec3 baseColor = colormap(aY * 0.5 + 0.5);
+    #else
         vec3 baseColor = vec3(0.5, 0.5, 0.8);
+    #endif
+        // The rotated graph looks down -z
+        float fogDepth = -(uNormalsRotation * vec4(aPosition.x, aY, aPosition.z, 1.0)).z;
 
-        vColor = vec4(baseColor * vLighting, 1.0);
+    #ifdef PER_PIXEL_LIGHTING
+        vNormal = transformedNormal;
+        vBaseColor = baseColor;
+        vFogDepth = fogDepth;
+    #else
+        vec3 color = baseColor * lighting(transformedNormal);
+    #ifdef FOG
+        color = applyFog(color, fogDepth);
+    #endif
+        vColor = vec4(color, 1.0);
+    #endif
     }
 "#;
@@ -0,0 +1,79 @@
+// Like graph_3d, but computes the heights and normals itself from one of the
+// programs::BuiltinSurface functions, so nothing is uploaded per frame
+pub const SHADER: &str = r#"
+    attribute vec4 aPosition;
+
+    uniform mat4 uProjection;
+    uniform mat4 uNormalsRotation;
+    uniform float uTime;
+    // programs::BuiltinSurface::shader_index
+    uniform float uSurface;
+    // x min, x max, z min, z max of the plot domain
+    uniform vec4 uDomain;
+    uniform vec2 uYRange;
+    // Size of a grid cell in display coordinates, along x and z
+    uniform vec2 uGridStep;
+    #ifdef PER_PIXEL_LIGHTING
+    // Lit by fragment::lit_surface
+    varying vec3 vNormal;
+    varying vec3 vBaseColor;
+    varying float vFogDepth;
+    #else
+    varying lowp vec4 vColor;
+    #endif
+
+    #include "lighting"
+    #include "colormap"
+    #include "fog"
+
+    float surface(float x, float z) {
+        if (uSurface < 0.5) {
+            return 0.15 * sin(sqrt(x * x + z * z) + uTime * 0.001);
+        } else if (uSurface < 1.5) {
+            return 0.2 * sin(x + uTime * 0.001) * cos(z);
+        }
+        return 0.02 * (x * x - z * z);
+    }
+
+    // Height in display coordinates at a display position in [-1, 1]
+    float displayHeight(vec2 position) {
+        vec2 fraction = position * 0.5 + 0.5;
+        float x = mix(uDomain.x, uDomain.y, fraction.x);
+        float z = mix(uDomain.z, uDomain.w, fraction.y);
+        return 2.0 * (surface(x, z) - uYRange.x) / (uYRange.y - uYRange.x) - 1.0;
+    }
+
+    void main() {
+        float y = displayHeight(aPosition.xz);
+        gl_Position = uProjection * vec4(aPosition.x, y, aPosition.z, 1.0);
+
+        // Central differences over one grid cell
+        vec2 stepX = vec2(uGridStep.x, 0.0);
+        vec2 stepZ = vec2(0.0, uGridStep.y);
+        float slopeX = (displayHeight(aPosition.xz + stepX) - displayHeight(aPosition.xz - stepX)) / (2.0 * uGridStep.x);
+        float slopeZ = (displayHeight(aPosition.xz + stepZ) - displayHeight(aPosition.xz - stepZ)) / (2.0 * uGridStep.y);
+        vec3 normal = normalize(vec3(-slopeX, 1.0, -slopeZ));
+
+        vec3 transformedNormal = (uNormalsRotation * vec4(normal, 1.0)).xyz;
+    #ifdef COLORMAP
+        // Heights are in [-1, 1] in display coordinates
+        vec3 baseColor = colormap(y * 0.5 + 0.5);
+    #else
+        vec3 baseColor = vec3(0.5, 0.5, 0.8);
+    #endif
+        // The rotated graph looks down -z
+        float fogDepth = -(uNormalsRotation * vec4(aPosition.x, y, aPosition.z, 1.0)).z;
+
+    #ifdef PER_PIXEL_LIGHTING
+        vNormal = transformedNormal;
+        vBaseColor = baseColor;
+        vFogDepth = fogDepth;
+    #else
+        vec3 color = baseColor * lighting(transformedNormal);
+    #ifdef FOG
+        color = applyFog(color, fogDepth);
+    #endif
+        vColor = vec4(color, 1.0);
+    #endif
+    }
+"#;
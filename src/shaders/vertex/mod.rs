@@ -1,3 +1,4 @@
 pub mod color_2d;
 pub mod color_2d_gradient;
 pub mod graph_3d;
+pub mod graph_3d_surface;
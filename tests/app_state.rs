@@ -0,0 +1,443 @@
+// Camera controls of AppState, driven the way the canvas event handlers drive them
+use std::f32::consts::FRAC_PI_2;
+use nalgebra::{UnitQuaternion, Vector3};
+use rust_3d_demo::app_state::{AppState, ViewPreset};
+use rust_3d_demo::common_funcs as cf;
+use rust_3d_demo::constants as c;
+
+const WIDTH: f32 = 800.;
+const HEIGHT: f32 = 600.;
+
+fn app_state() -> AppState {
+    let mut return_var = AppState::new();
+    return_var.update_dynamic_data(0., HEIGHT, WIDTH);
+    return_var
+}
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!((actual - expected).abs() < 1e-5, "{} != {}", actual, expected);
+}
+
+// Right-button drag by (dx, dy) pixels, y down like the browser
+fn pan_drag(state: &mut AppState, dx: f32, dy: f32) {
+    state.update_mouse_down(400., 300., true, true);
+    state.update_mouse_position(400. + dx, 300. + dy);
+    state.update_mouse_down(400. + dx, 300. + dy, false, false);
+}
+
+#[test]
+fn wheel_zooms_in_on_scroll_up_and_out_on_scroll_down() {
+    let mut state = app_state();
+    state.update_mouse_wheel(-100.);
+    assert_close(state.zoom, (100. * c::ZOOM_SPEED).exp());
+    state.update_mouse_wheel(200.);
+    assert_close(state.zoom, (-100. * c::ZOOM_SPEED).exp());
+}
+
+#[test]
+fn zoom_is_clamped() {
+    let mut state = app_state();
+    state.update_mouse_wheel(-1e6);
+    assert_eq!(state.zoom, c::MAX_ZOOM);
+    state.update_mouse_wheel(-100.);
+    assert_eq!(state.zoom, c::MAX_ZOOM);
+    // Zooming out starts from the clamped value, not from where the wheel would have been
+    state.update_mouse_wheel(100.);
+    assert!(state.zoom < c::MAX_ZOOM);
+
+    state.update_mouse_wheel(1e6);
+    assert_eq!(state.zoom, c::MIN_ZOOM);
+    state.update_mouse_wheel(f32::MAX);
+    assert_eq!(state.zoom, c::MIN_ZOOM);
+}
+
+#[test]
+fn pan_follows_the_cursor_in_half_canvas_heights() {
+    let mut state = app_state();
+    pan_drag(&mut state, 150., -60.);
+    assert_close(state.pan_x, 2. * 150. / HEIGHT);
+    // Dragging up moves the graph up, WebGL y points up
+    assert_close(state.pan_y, 2. * 60. / HEIGHT);
+}
+
+#[test]
+fn pan_delta_does_not_depend_on_zoom() {
+    for zoom in [c::MIN_ZOOM, 1., c::MAX_ZOOM] {
+        let mut state = app_state();
+        state.zoom = zoom;
+        pan_drag(&mut state, 30., 45.);
+        assert_close(state.pan_x, 2. * 30. / HEIGHT);
+        assert_close(state.pan_y, -2. * 45. / HEIGHT);
+        assert_eq!(state.zoom, zoom);
+    }
+}
+
+#[test]
+fn pan_is_clamped() {
+    let mut state = app_state();
+    pan_drag(&mut state, 10. * HEIGHT, -10. * HEIGHT);
+    assert_eq!((state.pan_x, state.pan_y), (c::MAX_PAN, c::MAX_PAN));
+    pan_drag(&mut state, -HEIGHT / 2., 0.);
+    assert_close(state.pan_x, c::MAX_PAN - 1.);
+}
+
+#[test]
+fn pan_drag_does_not_rotate() {
+    let mut state = app_state();
+    let rotation = state.rotation;
+    pan_drag(&mut state, 120., 80.);
+    assert_eq!(state.rotation, rotation);
+}
+
+#[test]
+fn pinch_scales_the_zoom_by_the_distance_ratio() {
+    let mut state = app_state();
+    state.update_touch_down(1, 300., 300.);
+    state.update_touch_down(2, 500., 300.);
+    state.update_touch_move(2, 600., 300.);
+    assert_close(state.zoom, 1.5);
+    state.update_touch_move(1, 500., 300.);
+    assert_close(state.zoom, 0.5);
+
+    state.update_touch_move(1, 599.9, 300.);
+    assert_eq!(state.zoom, c::MIN_ZOOM);
+    // Too close together to measure a ratio
+    state.update_touch_move(2, 700., 300.);
+    assert_eq!(state.zoom, c::MIN_ZOOM);
+}
+
+#[test]
+fn pinch_pans_by_the_midpoint_motion() {
+    let mut state = app_state();
+    state.update_touch_down(1, 300., 300.);
+    state.update_touch_down(2, 500., 300.);
+    // The midpoint moves 50 pixels right
+    state.update_touch_move(2, 600., 300.);
+    assert_close(state.pan_x, 2. * 50. / HEIGHT);
+    assert_close(state.pan_y, 0.);
+}
+
+#[test]
+fn two_finger_drag_pans_without_zooming_or_rotating() {
+    let mut state = app_state();
+    let rotation = state.rotation;
+    state.update_touch_down(1, 300., 300.);
+    state.update_touch_down(2, 500., 400.);
+    state.update_touch_move(1, 340., 280.);
+    state.update_touch_move(2, 540., 380.);
+    assert_close(state.zoom, 1.);
+    assert_close(state.pan_x, 2. * 40. / HEIGHT);
+    assert_close(state.pan_y, 2. * 20. / HEIGHT);
+    assert_eq!(state.rotation, rotation);
+}
+
+#[test]
+fn one_finger_drag_rotates() {
+    let mut state = app_state();
+    let rotation = state.rotation;
+    state.update_touch_down(1, 400., 300.);
+    state.update_touch_move(1, 450., 300.);
+    assert!(state.rotation.angle_to(&rotation) > 0.01);
+    assert_close(state.zoom, 1.);
+    assert_eq!((state.pan_x, state.pan_y), (0., 0.));
+}
+
+#[test]
+fn lifted_contact_no_longer_pinches() {
+    let mut state = app_state();
+    state.update_touch_down(1, 300., 300.);
+    state.update_touch_down(2, 500., 300.);
+    state.update_touch_up(1);
+    // The remaining finger rotates instead of zooming against the lifted one
+    let rotation = state.rotation;
+    state.update_touch_move(2, 600., 300.);
+    assert_close(state.zoom, 1.);
+    assert!(state.rotation.angle_to(&rotation) > 0.01);
+}
+
+#[test]
+fn third_contact_does_not_zoom() {
+    let mut state = app_state();
+    state.update_touch_down(1, 300., 300.);
+    state.update_touch_down(2, 500., 300.);
+    state.update_touch_down(3, 400., 100.);
+    state.update_touch_move(3, 400., 500.);
+    assert_close(state.zoom, 1.);
+    assert_eq!((state.pan_x, state.pan_y), (0., 0.));
+}
+
+#[test]
+fn move_of_an_unknown_contact_is_ignored() {
+    let mut state = app_state();
+    let rotation = state.rotation;
+    state.update_touch_move(7, 450., 300.);
+    assert_eq!(state.rotation, rotation);
+    assert!(state.touches.is_empty());
+}
+
+// Rotation added by a left-button drag, in browser coordinates
+fn drag_rotation(state: &mut AppState, from: (f32, f32), to: (f32, f32)) -> UnitQuaternion<f32> {
+    let before = state.rotation;
+    state.update_mouse_down(from.0, from.1, true, false);
+    state.update_mouse_position(to.0, to.1);
+    state.update_mouse_down(to.0, to.1, false, false);
+    state.rotation * before.inverse()
+}
+
+#[test]
+fn arcball_vector_is_on_the_unit_sphere() {
+    assert_eq!(cf::arcball_vector(10., 20., 10., 20., 5.), Vector3::z());
+    let inside = cf::arcball_vector(13., 20., 10., 20., 5.);
+    assert_close(inside.norm(), 1.);
+    assert_close(inside.x, 0.6);
+    assert_close(inside.z, 0.8);
+    assert_eq!(cf::arcball_vector(15., 20., 10., 20., 5.), Vector3::x());
+}
+
+#[test]
+fn arcball_vector_projects_outside_points_onto_the_rim() {
+    let outside = cf::arcball_vector(10. + 30., 20. + 40., 10., 20., 5.);
+    assert_close(outside.norm(), 1.);
+    assert_close(outside.x, 0.6);
+    assert_close(outside.y, 0.8);
+    assert_eq!(outside.z, 0.);
+}
+
+#[test]
+fn drag_from_the_center_to_the_edge_turns_a_quarter() {
+    let mut state = app_state();
+    // The sphere is centered in the canvas with a radius of half the display size
+    let radius = (state.control_top - state.control_bottom) / 2.;
+    let rotation = drag_rotation(&mut state, (400., 300.), (400. + radius, 300.));
+    assert_close(rotation.angle(), FRAC_PI_2);
+    // Pulling the front of the sphere to the right turns it around +y
+    assert_close(rotation.axis().unwrap().dot(&Vector3::y()), 1.);
+
+    // Upwards on the screen is down in browser coordinates, turning around -x
+    let rotation = drag_rotation(&mut state, (400., 300.), (400., 300. - radius));
+    assert_close(rotation.angle(), FRAC_PI_2);
+    assert_close(rotation.axis().unwrap().dot(&Vector3::x()), -1.);
+}
+
+#[test]
+fn drag_past_the_edge_turns_like_a_drag_to_the_rim() {
+    let mut state = app_state();
+    let radius = (state.control_top - state.control_bottom) / 2.;
+    let to_rim = drag_rotation(&mut state, (400., 300.), (400. + radius, 300.));
+    let past_rim = drag_rotation(&mut app_state(), (400., 300.), (400. + 3. * radius, 300.));
+    assert_close(to_rim.angle_to(&past_rim), 0.);
+}
+
+#[test]
+fn zero_length_drag_does_not_rotate() {
+    let mut state = app_state();
+    let rotation = state.rotation;
+    for from in [(400., 300.), (500., 250.), (2000., -50.)] {
+        drag_rotation(&mut state, from, from);
+        assert_eq!(state.rotation, rotation);
+    }
+    state.update_dynamic_data(16., HEIGHT, WIDTH);
+    assert_eq!(state.angular_velocity, Vector3::zeros());
+}
+
+// Left-button drag over `frames` frames of 16 ms, starting at state.time
+fn flick(state: &mut AppState, frames: usize, step_x: f32) {
+    state.update_mouse_down(400., 300., true, false);
+    for frame in 1..=frames {
+        state.update_mouse_position(400. + frame as f32 * step_x, 300.);
+        state.update_dynamic_data(state.time + 16., HEIGHT, WIDTH);
+    }
+}
+
+fn release(state: &mut AppState) {
+    state.update_mouse_down(state.mouse_x, HEIGHT - state.mouse_y, false, false);
+}
+
+#[test]
+fn released_drag_keeps_spinning_and_slows_down() {
+    let mut state = app_state();
+    flick(&mut state, 5, 10.);
+    release(&mut state);
+    let mut speed = state.angular_velocity.norm();
+    assert!(speed > c::MIN_ANGULAR_VELOCITY);
+    for _ in 0..20 {
+        let rotation = state.rotation;
+        state.update_dynamic_data(state.time + 16., HEIGHT, WIDTH);
+        assert!(state.rotation.angle_to(&rotation) > 0.);
+        let slower = state.angular_velocity.norm();
+        assert!(slower < speed, "{} after {}", slower, speed);
+        // Exponential decay at rotation_damping per second
+        assert_close(slower / speed, (-c::DEFAULT_ROTATION_DAMPING * 0.016).exp());
+        speed = slower;
+    }
+}
+
+#[test]
+fn spin_stops_below_the_minimum_velocity() {
+    let mut state = app_state();
+    state.rotation_damping = 50.;
+    flick(&mut state, 5, 10.);
+    release(&mut state);
+    let mut frames = 0;
+    while state.angular_velocity != Vector3::zeros() {
+        state.update_dynamic_data(state.time + 16., HEIGHT, WIDTH);
+        frames += 1;
+        assert!(frames < 1000, "still spinning at {}", state.angular_velocity.norm());
+    }
+    let rotation = state.rotation;
+    state.update_dynamic_data(state.time + 16., HEIGHT, WIDTH);
+    assert_eq!(state.rotation, rotation);
+}
+
+#[test]
+fn zero_damping_spins_forever() {
+    let mut state = app_state();
+    state.rotation_damping = 0.;
+    flick(&mut state, 5, 10.);
+    release(&mut state);
+    let speed = state.angular_velocity.norm();
+    for _ in 0..100 {
+        state.update_dynamic_data(state.time + 16., HEIGHT, WIDTH);
+    }
+    assert_close(state.angular_velocity.norm(), speed);
+}
+
+#[test]
+fn click_without_motion_does_not_spin() {
+    let mut state = app_state();
+    state.update_mouse_down(450., 250., true, false);
+    state.update_dynamic_data(16., HEIGHT, WIDTH);
+    release(&mut state);
+    let rotation = state.rotation;
+    state.update_dynamic_data(32., HEIGHT, WIDTH);
+    assert_eq!(state.angular_velocity, Vector3::zeros());
+    assert_eq!(state.rotation, rotation);
+}
+
+#[test]
+fn drag_held_still_before_release_does_not_spin() {
+    let mut state = app_state();
+    flick(&mut state, 5, 10.);
+    assert!(state.angular_velocity.norm() > c::MIN_ANGULAR_VELOCITY);
+    // Keep the button down without moving for a few frames
+    for _ in 0..4 {
+        state.update_dynamic_data(state.time + 16., HEIGHT, WIDTH);
+    }
+    release(&mut state);
+    let rotation = state.rotation;
+    state.update_dynamic_data(state.time + 16., HEIGHT, WIDTH);
+    assert_eq!(state.angular_velocity, Vector3::zeros());
+    assert_eq!(state.rotation, rotation);
+}
+
+#[test]
+fn grabbing_stops_the_spin() {
+    let mut state = app_state();
+    flick(&mut state, 5, 10.);
+    release(&mut state);
+    state.update_mouse_down(400., 300., true, false);
+    assert_eq!(state.angular_velocity, Vector3::zeros());
+}
+
+// Direction of the graph axis `axis` after rotating, z points towards the viewer
+fn facing(state: &AppState, axis: Vector3<f32>) -> Vector3<f32> {
+    state.rotation * axis
+}
+
+fn assert_vector_close(actual: Vector3<f32>, expected: Vector3<f32>) {
+    assert!((actual - expected).norm() < 1e-5, "{:?} != {:?}", actual, expected);
+}
+
+#[test]
+fn view_presets_turn_the_named_axis_towards_the_viewer() {
+    let mut state = app_state();
+    state.set_view_preset(ViewPreset::Top);
+    assert_vector_close(facing(&state, Vector3::y()), Vector3::z());
+    assert_vector_close(facing(&state, Vector3::x()), Vector3::x());
+
+    state.set_view_preset(ViewPreset::Front);
+    assert_eq!(state.rotation, UnitQuaternion::identity());
+
+    state.set_view_preset(ViewPreset::Side);
+    assert_vector_close(facing(&state, Vector3::x()), Vector3::z());
+    assert_vector_close(facing(&state, Vector3::y()), Vector3::y());
+
+    state.set_view_preset(ViewPreset::Isometric);
+    assert_vector_close(facing(&state, Vector3::repeat(1.).normalize()), Vector3::z());
+    // Upright: the y axis stays in the vertical plane of the screen
+    assert_close(facing(&state, Vector3::y()).x, 0.);
+}
+
+#[test]
+fn view_presets_keep_zoom_and_pan_except_reset() {
+    let mut state = app_state();
+    let initial = state.rotation;
+    state.zoom = 2.;
+    pan_drag(&mut state, 60., 0.);
+    flick(&mut state, 5, 10.);
+    release(&mut state);
+
+    state.set_view_preset(ViewPreset::Front);
+    assert_eq!(state.zoom, 2.);
+    assert_close(state.pan_x, 2. * 60. / HEIGHT);
+    // Presets stop the spin so the view stays put
+    assert_eq!(state.angular_velocity, Vector3::zeros());
+
+    state.set_view_preset(ViewPreset::Reset);
+    assert_eq!(state.rotation, initial);
+    assert_eq!((state.zoom, state.pan_x, state.pan_y), (1., 0., 0.));
+}
+
+#[test]
+fn view_presets_parse_from_their_names() {
+    assert_eq!("top".parse(), Ok(ViewPreset::Top));
+    assert_eq!("isometric".parse(), Ok(ViewPreset::Isometric));
+    assert!("bottom".parse::<ViewPreset>().unwrap_err().contains("bottom"));
+}
+
+#[test]
+fn fit_to_data_centers_and_scales_the_bounding_box() {
+    let mut state = app_state();
+    state.set_view_preset(ViewPreset::Front);
+    // x spans [-1, 3] and y spans [0, 1] on screen
+    let positions = [-1., 0., 0., 3., 0., 0., 1., 0., 2.];
+    state.fit_to_data(&positions, &[0., 1., 0.5]);
+
+    // The wider x extent, 2 either side of the center, fills FIT_TO_DATA_FILL of the area
+    assert_close(state.zoom, c::FIT_TO_DATA_FILL / 2.);
+    // The center (1, 0.5) moves to the middle, in units of half the canvas height
+    let graph_height = state.control_top - state.control_bottom - 2. * c::GRAPH_MARGIN;
+    let scale = state.zoom * graph_height / HEIGHT;
+    assert_close(state.pan_x, -scale);
+    assert_close(state.pan_y, -scale * 0.5);
+}
+
+#[test]
+fn fit_to_data_uses_the_current_rotation() {
+    let mut state = app_state();
+    state.set_view_preset(ViewPreset::Side);
+    // Seen from the side, z runs along the screen x axis and x along the view direction
+    let positions = [-1., 0., -0.5, 3., 0., 0.5];
+    state.fit_to_data(&positions, &[0., 0.]);
+    assert_close(state.zoom, c::FIT_TO_DATA_FILL / 0.5);
+    assert_close(state.pan_x, 0.);
+    assert_close(state.pan_y, 0.);
+}
+
+#[test]
+fn fit_to_data_clamps_the_zoom() {
+    let mut state = app_state();
+    state.fit_to_data(&[0., 0., 0., 1e-4, 0., 0.], &[0., 0.]);
+    assert_eq!(state.zoom, c::MAX_ZOOM);
+    state.fit_to_data(&[-100., 0., 0., 100., 0., 0.], &[0., 0.]);
+    assert_eq!(state.zoom, c::MIN_ZOOM);
+}
+
+#[test]
+fn fit_to_data_without_finite_heights_keeps_the_view() {
+    let mut state = app_state();
+    state.zoom = 3.;
+    state.fit_to_data(&[], &[]);
+    state.fit_to_data(&[0., 0., 0.], &[f32::NAN]);
+    assert_eq!((state.zoom, state.pan_x, state.pan_y), (3., 0., 0.));
+}
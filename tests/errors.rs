@@ -0,0 +1,123 @@
+// Errors the programs report instead of panicking, checked with the software backend
+use nalgebra::UnitQuaternion;
+use rust_3d_demo::backend::software::SoftwareBackend;
+use rust_3d_demo::backend::RenderBackend;
+use rust_3d_demo::common_funcs as cf;
+use rust_3d_demo::constants as c;
+use rust_3d_demo::error::{Error, ShaderStage};
+use rust_3d_demo::programs::{Color2D, Color2DGradient, Graph3D, Light};
+use rust_3d_demo::shaders::{fragment, vertex};
+
+#[test]
+fn programs_link_on_the_software_backend() {
+    let gl = SoftwareBackend::new(64, 64);
+    assert!(Color2D::new(&gl).is_ok());
+    assert!(Color2DGradient::new(&gl).is_ok());
+    assert!(Graph3D::new(&gl).is_ok());
+}
+
+#[test]
+fn unknown_shader_pair_fails_to_link() {
+    let gl = SoftwareBackend::new(64, 64);
+    let result = gl.link_program("void main() {}", "void main() {}", &[], &[]);
+    assert!(matches!(result, Err(Error::Link(_))));
+}
+
+// Without the varyings of lit_surface, like a driver would
+#[test]
+fn per_pixel_lighting_needs_the_lit_fragment_shader() {
+    let gl = SoftwareBackend::new(64, 64);
+    let defines = &["PER_PIXEL_LIGHTING"];
+    let result = gl.link_program(vertex::graph_3d::SHADER, fragment::varying_color_from_vertex::SHADER, defines, &[]);
+    assert!(matches!(result, Err(Error::Link(_))));
+    assert!(gl.link_program(vertex::graph_3d::SHADER, fragment::lit_surface::SHADER, defines, &[]).is_ok());
+}
+
+#[test]
+fn invalid_light_is_rejected() {
+    let gl = SoftwareBackend::new(64, 64);
+    let mut graph = Graph3D::new(&gl).unwrap();
+    for light in [
+        Light { direction: [0., 0., 0.], ..Light::default() },
+        Light { specular: -1., ..Light::default() },
+        Light { shininess: f32::NAN, ..Light::default() },
+        Light { ambient_color: [0., -0.1, 0.], ..Light::default() },
+    ] {
+        assert!(matches!(graph.set_light(light), Err(Error::InvalidOption(_))));
+    }
+    assert_eq!(graph.light(), Light::default());
+}
+
+#[test]
+fn wrong_number_of_heights_is_a_mesh_error() {
+    let gl = SoftwareBackend::new(64, 64);
+    let graph = Graph3D::with_grid_size(&gl, cf::GridSize::new(4, 4).unwrap()).unwrap();
+    assert!(matches!(graph.set_y_values(&gl, &[0.; 24]), Err(Error::Mesh(_))));
+}
+
+#[test]
+fn render_without_heights_is_a_mesh_error() {
+    let gl = SoftwareBackend::new(64, 64);
+    let mut graph = Graph3D::with_grid_size(&gl, cf::GridSize::new(4, 4).unwrap()).unwrap();
+    let render = |graph: &Graph3D<SoftwareBackend>| graph.render(&gl, 0., 64., 0., 64., 64., 64., &UnitQuaternion::identity(), 1., 0., 0., 0.);
+    assert!(matches!(render(&graph), Err(Error::Mesh(_))));
+    graph.set_y_values(&gl, &[0.; 25]).unwrap();
+    assert!(render(&graph).is_ok());
+    // The normals depend on the mode, and the heights on the grid size
+    graph.set_normal_mode(cf::NormalMode::AreaWeighted);
+    assert!(matches!(render(&graph), Err(Error::Mesh(_))));
+    graph.set_y_values(&gl, &[0.; 25]).unwrap();
+    graph.set_grid_size(&gl, cf::GridSize::new(2, 2).unwrap()).unwrap();
+    assert!(matches!(render(&graph), Err(Error::Mesh(_))));
+}
+
+#[test]
+fn too_big_grid_without_32_bit_indices_is_a_mesh_error() {
+    let gl = SoftwareBackend::new(64, 64);
+    gl.set_element_index_uint_supported(false);
+    let result = Graph3D::with_grid_size(&gl, cf::GridSize::new(70_000, 1).unwrap());
+    assert!(matches!(result, Err(Error::Mesh(_))));
+}
+
+#[test]
+fn grid_size_is_bounded() {
+    assert!(cf::GridSize::new(0, 10).is_err());
+    assert!(cf::GridSize::new(10, 0).is_err());
+    // Would overflow usize on wasm32 and 64-bit targets alike
+    assert!(cf::GridSize::new(usize::MAX, 2).is_err());
+    assert!(cf::GridSize::new(1 << 16, 1 << 16).is_err());
+    assert!(cf::GridSize::new(c::MAX_GRID_CELLS + 1, 1).is_err());
+    assert!(cf::GridSize::square(2001).is_err());
+
+    let grid_size = cf::GridSize::new(c::MAX_GRID_CELLS, 1).unwrap();
+    assert_eq!((grid_size.x(), grid_size.z()), (c::MAX_GRID_CELLS, 1));
+    assert_eq!(grid_size.cell_count(), c::MAX_GRID_CELLS);
+    assert_eq!(grid_size.vertex_count(), 2 * (c::MAX_GRID_CELLS + 1));
+    assert!(cf::GridSize::square(2000).is_ok());
+}
+
+#[test]
+fn grid_mesh_matches_the_grid_size() {
+    let grid_size = cf::GridSize::new(3, 2).unwrap();
+    assert_eq!(cf::get_grid_positions(grid_size).len(), 3 * 4 * 3);
+    assert_eq!(cf::get_grid_indices(grid_size).len(), 6 * 3 * 2);
+}
+
+#[test]
+fn shader_compile_error_quotes_the_failing_line() {
+    let error = Error::shader_compile(
+        ShaderStage::Fragment,
+        String::from("ERROR: 0:3: 'vColour' : undeclared identifier\n"),
+        String::from("precision mediump float;\nvoid main() {\n    gl_FragColor = vColour;\n}"),
+    );
+    assert_eq!(
+        error.to_string(),
+        "The fragment shader failed to compile:\n\
+         error: 'vColour' : undeclared identifier\n\
+         \x20 --> line 3\n\
+         \x20 |\n\
+         2 | void main() {\n\
+         3 |     gl_FragColor = vColour;\n\
+         \x20 |     ^^^^^^^^^^^^^^^^^^^^^^^"
+    );
+}
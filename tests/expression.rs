@@ -0,0 +1,106 @@
+// Parsing and evaluation of surface expressions, and the errors for invalid input
+use rust_3d_demo::expression::Expression;
+
+fn eval(source: &str) -> f32 {
+    Expression::parse(source).unwrap().eval(0.5, 2., 1000.)
+}
+
+fn error(source: &str) -> String {
+    Expression::parse(source).unwrap_err()
+}
+
+#[test]
+fn operators_follow_the_usual_precedence() {
+    assert_eq!(eval("1 + 2 * 3"), 7.);
+    assert_eq!(eval("(1 + 2) * 3"), 9.);
+    assert_eq!(eval("8 / 4 / 2"), 1.);
+    assert_eq!(eval("10 - 4 - 3"), 3.);
+    assert_eq!(eval("2 * 3 ^ 2"), 18.);
+}
+
+#[test]
+fn power_is_right_associative() {
+    assert_eq!(eval("2 ^ 3 ^ 2"), 512.);
+    assert_eq!(eval("(2 ^ 3) ^ 2"), 64.);
+    assert_eq!(eval("2 ^ -1"), 0.5);
+}
+
+#[test]
+fn unary_minus_binds_looser_than_power() {
+    assert_eq!(eval("-2 ^ 2"), -4.);
+    assert_eq!(eval("(-2) ^ 2"), 4.);
+    assert_eq!(eval("--3"), 3.);
+    assert_eq!(eval("+-x"), -0.5);
+    assert_eq!(eval("2 * -z"), -4.);
+}
+
+#[test]
+fn numbers_accept_scientific_notation() {
+    assert_eq!(eval("1.5e-3"), 0.0015);
+    assert_eq!(eval("2E3"), 2000.);
+    assert_eq!(eval(".5e+1"), 5.);
+    // A bare "e" after a number is the constant, which needs an operator
+    assert!(Expression::parse("2e").is_err());
+    assert_eq!(eval("2 * e"), 2. * std::f32::consts::E);
+}
+
+#[test]
+fn variables_constants_and_functions() {
+    assert_eq!(eval("x + z"), 2.5);
+    assert_eq!(eval("t * 0.001"), 1.);
+    assert_eq!(eval("sin(pi / 2)"), 1.);
+    assert_eq!(eval("SQRT(Z * 8)"), 4.);
+    assert!((eval("ln(e)") - 1.).abs() < 1e-6);
+    assert!(eval("log(-x)").is_nan());
+}
+
+#[test]
+fn time_dependence_is_detected() {
+    for source in ["t", "sin(x + t * 0.001)", "-t", "x ^ (2 + t)"] {
+        assert!(Expression::parse(source).unwrap().uses_time(), "{}", source);
+    }
+    for source in ["x * z", "cos(pi * x) - e", "2"] {
+        assert!(!Expression::parse(source).unwrap().uses_time(), "{}", source);
+    }
+}
+
+#[test]
+fn unknown_identifiers_are_errors() {
+    assert_eq!(error("y + 1"), "Unknown identifier 'y' at position 0");
+    assert_eq!(error("x * foo(z)"), "Unknown identifier 'foo' at position 4");
+    assert_eq!(error("sin x"), "Expected '(' after 'sin' at position 4");
+}
+
+#[test]
+fn malformed_input_is_an_error() {
+    assert_eq!(error("(x + 1"), "Expected ')' at end of expression");
+    assert_eq!(error("x + 1)"), "Unexpected token at position 5");
+    assert_eq!(error("sin(x"), "Expected ')' at end of expression");
+    assert_eq!(error("x +"), "Unexpected end of expression");
+    assert_eq!(error("x $ 2"), "Unexpected character '$' at position 2");
+    assert_eq!(error("1.2.3"), "Invalid number '1.2.3' at position 0");
+    assert_eq!(error("   "), "Expression is empty");
+    assert_eq!(error("* x"), "Unexpected token at position 0");
+}
+
+#[test]
+fn deep_nesting_is_an_error_instead_of_a_stack_overflow() {
+    let parentheses = format!("{}x{}", "(".repeat(100_000), ")".repeat(100_000));
+    assert!(error(&parentheses).starts_with("Expression is nested more than 128 levels deep"));
+    let minuses = format!("{}x", "-".repeat(100_000));
+    assert!(error(&minuses).starts_with("Expression is nested more than 128 levels deep"));
+    let powers = format!("x{}", "^x".repeat(100_000));
+    assert!(error(&powers).starts_with("Expression is nested more than 128 levels deep"));
+    let calls = format!("{}x{}", "sin(".repeat(100_000), ")".repeat(100_000));
+    assert!(error(&calls).starts_with("Expression is nested more than 128 levels deep"));
+    // Long chains nest the tree too
+    let sum = format!("x{}", " + x".repeat(100_000));
+    assert!(error(&sum).starts_with("Expression is nested more than 128 levels deep"));
+}
+
+#[test]
+fn reasonable_nesting_is_accepted() {
+    assert_eq!(eval(&format!("{}x{}", "(".repeat(60), ")".repeat(60))), 0.5);
+    assert_eq!(eval(&format!("x{}", " + x".repeat(60))), 30.5);
+    assert_eq!(eval(&format!("{}x", "-".repeat(60))), 0.5);
+}
@@ -0,0 +1,483 @@
+// Golden-image tests for the 3D graph
+// Scenes are drawn with the software backend and compared against the PNGs in tests/golden.
+// On mismatch the rendered image and a diff image are written to target/golden-diffs.
+// After an intentional change to the picture, regenerate the references with
+//
+//   UPDATE_GOLDEN=1 cargo test --test golden
+//
+// The software backend draws with CPU ports of the shaders, so editing the GLSL fails
+// ported_shader_sources_are_unchanged until the port and its pinned hash are updated too
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use web_sys::WebGlRenderingContext as GL;
+use rust_3d_demo::app_state::AppState;
+use rust_3d_demo::backend::software::{builtin_shader, builtin_surface_height, SoftwareBackend, PORTED_SOURCES};
+use rust_3d_demo::backend::RenderBackend;
+use rust_3d_demo::common_funcs as cf;
+use rust_3d_demo::constants as c;
+use rust_3d_demo::error::Error;
+use rust_3d_demo::expression::Expression;
+use rust_3d_demo::gl_setup;
+use rust_3d_demo::plot_domain::{AxisRange, PlotDomain};
+use rust_3d_demo::shaders::preprocessor::preprocess;
+use rust_3d_demo::programs::{BuiltinSurface, Graph3D, LightingModel, SurfaceStyle};
+
+const WIDTH: usize = 320;
+const HEIGHT: usize = 240;
+// Largest per-channel difference that still counts as a match
+const CHANNEL_TOLERANCE: u8 = 3;
+// Fraction of pixels allowed to exceed CHANNEL_TOLERANCE (edge pixels can flip)
+const MAX_MISMATCHED_FRACTION: f32 = 0.002;
+
+struct Scene {
+    expression: &'static str,
+    time: f32,
+    rotation_angle_x_axis: f32,
+    rotation_angle_y_axis: f32,
+    orthographic: bool,
+    style: SurfaceStyle,
+    domain: PlotDomain,
+}
+
+impl Default for Scene {
+    // The initial view of the Client, with the heights drawn unscaled
+    fn default() -> Self {
+        Self {
+            expression: c::DEFAULT_SURFACE_EXPRESSION,
+            time: 0.,
+            rotation_angle_x_axis: -0.5,
+            rotation_angle_y_axis: 0.5,
+            orthographic: false,
+            style: SurfaceStyle::default(),
+            domain: PlotDomain { y: Some(AxisRange::new(-1., 1.).unwrap()), ..PlotDomain::default() },
+        }
+    }
+}
+
+fn render_scene(scene: &Scene) -> Vec<u8> {
+    render_scene_with_grid(scene, cf::GridSize::square(c::DEFAULT_GRID_SIZE).unwrap(), true)
+}
+
+fn render_scene_with_grid(scene: &Scene, grid_size: cf::GridSize, element_index_uint: bool) -> Vec<u8> {
+    let gl = SoftwareBackend::new(WIDTH, HEIGHT);
+    gl.set_element_index_uint_supported(element_index_uint);
+    gl_setup::configure_context(&gl);
+    let mut graph = Graph3D::with_grid_size(&gl, grid_size).unwrap();
+    graph.set_style(&gl, scene.style).unwrap();
+    draw_scene(&gl, &graph, scene)
+}
+
+fn draw_scene(gl: &SoftwareBackend, graph: &Graph3D<SoftwareBackend>, scene: &Scene) -> Vec<u8> {
+    let surface = Expression::parse(scene.expression).unwrap();
+
+    let mut state = AppState::new();
+    state.update_dynamic_data(scene.time, HEIGHT as f32, WIDTH as f32);
+
+    gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
+    graph.set_y_values(gl, &cf::get_updated_y_values(state.time, graph.grid_size(), &scene.domain, &surface)).unwrap();
+    graph.render(
+        gl,
+        state.control_bottom + c::GRAPH_MARGIN,
+        state.control_top - c::GRAPH_MARGIN,
+        state.control_left + c::GRAPH_MARGIN,
+        state.control_right - c::GRAPH_MARGIN,
+        state.canvas_height,
+        state.canvas_width,
+        &cf::rotation_from_euler_angles(scene.rotation_angle_x_axis, scene.rotation_angle_y_axis),
+        state.zoom,
+        state.pan_x,
+        state.pan_y,
+        if scene.orthographic { 1. } else { 0. },
+    ).unwrap();
+    gl.read_pixels()
+}
+
+// Same scene with the heights computed by the surface shader, in the y range it picks
+fn render_builtin_scene(scene: &Scene, surface: BuiltinSurface) -> Vec<u8> {
+    let gl = SoftwareBackend::new(WIDTH, HEIGHT);
+    gl_setup::configure_context(&gl);
+    let mut graph = Graph3D::new(&gl).unwrap();
+    graph.set_style(&gl, scene.style).unwrap();
+
+    let mut state = AppState::new();
+    state.update_dynamic_data(scene.time, HEIGHT as f32, WIDTH as f32);
+
+    gl.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
+    graph.render_builtin(
+        &gl,
+        state.control_bottom + c::GRAPH_MARGIN,
+        state.control_top - c::GRAPH_MARGIN,
+        state.control_left + c::GRAPH_MARGIN,
+        state.control_right - c::GRAPH_MARGIN,
+        state.canvas_height,
+        state.canvas_width,
+        &cf::rotation_from_euler_angles(scene.rotation_angle_x_axis, scene.rotation_angle_y_axis),
+        state.zoom,
+        state.pan_x,
+        state.pan_y,
+        if scene.orthographic { 1. } else { 0. },
+        surface,
+        state.time,
+        &scene.domain,
+        surface.y_range(&scene.domain).unwrap(),
+    );
+    gl.read_pixels()
+}
+
+fn read_png(path: &Path) -> (usize, usize, Vec<u8>) {
+    let decoder = png::Decoder::new(File::open(path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!(info.color_type, png::ColorType::Rgba, "{} must be an RGBA image", path.display());
+    pixels.truncate(info.buffer_size());
+    (info.width as usize, info.height as usize, pixels)
+}
+
+fn write_png(path: &Path, width: usize, height: usize, pixels: &[u8]) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let file = File::create(path).unwrap();
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header().unwrap().write_image_data(pixels).unwrap();
+}
+
+// Mismatched pixels in red over a dimmed copy of the reference
+fn diff_image(expected: &[u8], actual: &[u8]) -> (usize, Vec<u8>) {
+    let mut mismatched = 0;
+    let mut diff = Vec::with_capacity(expected.len());
+    for (e, a) in expected.chunks_exact(4).zip(actual.chunks_exact(4)) {
+        let differs = e.iter().zip(a.iter()).any(|(e, a)| e.abs_diff(*a) > CHANNEL_TOLERANCE);
+        if differs {
+            mismatched += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            diff.extend_from_slice(&[e[0] / 4, e[1] / 4, e[2] / 4, 255]);
+        }
+    }
+    (mismatched, diff)
+}
+
+fn check_golden(name: &str, scene: &Scene) {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let reference_path = root.join("tests/golden").join(format!("{}.png", name));
+    let actual = render_scene(scene);
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        write_png(&reference_path, WIDTH, HEIGHT, &actual);
+        return;
+    }
+
+    assert!(
+        reference_path.exists(),
+        "Missing reference image {}, run with UPDATE_GOLDEN=1 to create it",
+        reference_path.display()
+    );
+    let (width, height, expected) = read_png(&reference_path);
+    assert_eq!((width, height), (WIDTH, HEIGHT), "Reference image {} has the wrong size", name);
+
+    let (mismatched, diff) = diff_image(&expected, &actual);
+    let mismatched_fraction = mismatched as f32 / (WIDTH * HEIGHT) as f32;
+    if mismatched_fraction > MAX_MISMATCHED_FRACTION {
+        let output_dir = root.join("target/golden-diffs");
+        write_png(&output_dir.join(format!("{}.actual.png", name)), WIDTH, HEIGHT, &actual);
+        write_png(&output_dir.join(format!("{}.diff.png", name)), WIDTH, HEIGHT, &diff);
+        panic!(
+            "{} differs from its reference in {} pixels ({:.2}%), see {}",
+            name,
+            mismatched,
+            100. * mismatched_fraction,
+            output_dir.display()
+        );
+    }
+}
+
+#[test]
+fn default_ripple() {
+    check_golden("default_ripple", &Scene::default());
+}
+
+#[test]
+fn ripple_later_in_time() {
+    check_golden("ripple_later_in_time", &Scene {
+        time: 2500.,
+        ..Scene::default()
+    });
+}
+
+#[test]
+fn saddle_steep_angle() {
+    check_golden("saddle_steep_angle", &Scene {
+        expression: "0.02 * (x^2 - z^2)",
+        rotation_angle_x_axis: -1.2,
+        rotation_angle_y_axis: -0.8,
+        ..Scene::default()
+    });
+}
+
+#[test]
+fn waves_from_below() {
+    check_golden("waves_from_below", &Scene {
+        expression: "0.2 * sin(x) * cos(z)",
+        rotation_angle_x_axis: 0.6,
+        rotation_angle_y_axis: 2.,
+        ..Scene::default()
+    });
+}
+
+#[test]
+fn saddle_orthographic() {
+    check_golden("saddle_orthographic", &Scene {
+        expression: "0.02 * (x^2 - z^2)",
+        rotation_angle_x_axis: -1.2,
+        rotation_angle_y_axis: -0.8,
+        orthographic: true,
+        ..Scene::default()
+    });
+}
+
+#[test]
+fn custom_domain_auto_y_range() {
+    check_golden("custom_domain_auto_y_range", &Scene {
+        expression: "sqrt(x) * sin(z)",
+        domain: PlotDomain {
+            x: AxisRange::new(0., 50.).unwrap(),
+            z: AxisRange::new(-3., 3.).unwrap(),
+            y: None,
+        },
+        ..Scene::default()
+    });
+}
+
+// Fades to the black the context is cleared with
+#[test]
+fn saddle_colormap_with_fog() {
+    check_golden("saddle_colormap_with_fog", &Scene {
+        expression: "0.02 * (x^2 - z^2)",
+        style: SurfaceStyle { colormap: true, fog: Some([0., 0., 0.]), lighting: LightingModel::Lambert },
+        ..Scene::default()
+    });
+}
+
+#[test]
+fn saddle_phong() {
+    check_golden("saddle_phong", &Scene {
+        expression: "0.02 * (x^2 - z^2)",
+        style: SurfaceStyle { lighting: LightingModel::Phong, ..SurfaceStyle::default() },
+        ..Scene::default()
+    });
+}
+
+#[test]
+fn ripple_blinn_phong_colormap() {
+    check_golden("ripple_blinn_phong_colormap", &Scene {
+        style: SurfaceStyle { colormap: true, fog: None, lighting: LightingModel::BlinnPhong },
+        ..Scene::default()
+    });
+}
+
+// Above 65536 vertices the mesh needs 32-bit indices, or else is drawn in chunks
+#[test]
+fn chunked_mesh_matches_32_bit_indices() {
+    let scene = Scene::default();
+    let grid_size = cf::GridSize::new(300, 280).unwrap();
+    let with_u32 = render_scene_with_grid(&scene, grid_size, true);
+    let chunked = render_scene_with_grid(&scene, grid_size, false);
+    let (mismatched, _) = diff_image(&with_u32, &chunked);
+    assert_eq!(mismatched, 0);
+}
+
+// With two vertex rows per chunk, the widest grid that u16 indices can still draw, two
+// cells deep so it needs two chunks. Seen from straight above so the sliver triangles
+// have small bounding boxes
+#[test]
+fn widest_chunked_mesh_matches_32_bit_indices() {
+    let scene = Scene {
+        rotation_angle_x_axis: -std::f32::consts::FRAC_PI_2,
+        rotation_angle_y_axis: 0.,
+        orthographic: true,
+        ..Scene::default()
+    };
+    let grid_size = cf::GridSize::new(32767, 2).unwrap();
+    let with_u32 = render_scene_with_grid(&scene, grid_size, true);
+    assert!(with_u32.chunks(4).any(|pixel| pixel[..3] != [0, 0, 0]));
+    let chunked = render_scene_with_grid(&scene, grid_size, false);
+    let (mismatched, _) = diff_image(&with_u32, &chunked);
+    assert_eq!(mismatched, 0);
+}
+
+// A single row of more than 65536 vertices cannot be chunked
+#[test]
+fn wider_grids_need_32_bit_indices() {
+    let gl = SoftwareBackend::new(WIDTH, HEIGHT);
+    gl.set_element_index_uint_supported(false);
+    for x in [32768, 65535, 65536, 100_000] {
+        let result = Graph3D::with_grid_size(&gl, cf::GridSize::new(x, 2).unwrap());
+        assert!(matches!(result, Err(Error::Mesh(_))), "{} cells wide", x);
+    }
+}
+
+// The expression the CPU path draws is the function the surface shader evaluates
+#[test]
+fn builtin_surface_expressions_match_the_shader_port() {
+    for surface in [BuiltinSurface::Ripple, BuiltinSurface::Waves, BuiltinSurface::Saddle] {
+        let expression = Expression::parse(surface.expression()).unwrap();
+        for time in [0., 1200., 987654.] {
+            for i in 0..=8 {
+                for j in 0..=8 {
+                    let (x, z) = (-7. + 1.75 * j as f32, -7. + 1.75 * i as f32);
+                    let expected = builtin_surface_height(surface, x, z, time);
+                    let actual = expression.eval(x, z, time);
+                    assert!(
+                        (actual - expected).abs() < 1e-5,
+                        "{:?} at x = {}, z = {}, t = {}: {} != {}",
+                        surface,
+                        x,
+                        z,
+                        time,
+                        actual,
+                        expected
+                    );
+                }
+            }
+        }
+    }
+}
+
+// The GPU path only differs in the normals along the border, which it takes from
+// outside the domain instead of one-sided differences
+#[test]
+fn builtin_surface_matches_expression() {
+    let styles = [
+        SurfaceStyle::default(),
+        SurfaceStyle { colormap: true, fog: Some([0.2, 0.2, 0.2]), lighting: LightingModel::Lambert },
+        SurfaceStyle { colormap: true, fog: Some([0.2, 0.2, 0.2]), lighting: LightingModel::BlinnPhong },
+    ];
+    for (surface, style) in [BuiltinSurface::Ripple, BuiltinSurface::Waves, BuiltinSurface::Saddle]
+        .into_iter()
+        .flat_map(|surface| styles.map(|style| (surface, style)))
+    {
+        let scene = Scene {
+            expression: surface.expression(),
+            time: 1200.,
+            style,
+            ..Scene::default()
+        };
+        let (mismatched, _) = diff_image(&render_scene(&scene), &render_builtin_scene(&scene, surface));
+        let mismatched_fraction = mismatched as f32 / (WIDTH * HEIGHT) as f32;
+        assert!(
+            mismatched_fraction <= MAX_MISMATCHED_FRACTION,
+            "{:?} with {:?} differs from its expression in {} pixels",
+            surface,
+            style,
+            mismatched
+        );
+    }
+}
+
+// With the default automatic y range the GPU path draws in the bounds of the function. The
+// heights are stretched several times more than in [-1, 1], and so are the differences in the
+// border normals, hence the larger tolerance
+#[test]
+fn builtin_surface_with_the_default_domain_matches_expression() {
+    for surface in [BuiltinSurface::Ripple, BuiltinSurface::Waves, BuiltinSurface::Saddle] {
+        let scene = Scene {
+            expression: surface.expression(),
+            time: 1200.,
+            domain: PlotDomain::default(),
+            ..Scene::default()
+        };
+        let y_range = surface.y_range(&scene.domain).unwrap();
+        let fixed_scene = Scene { domain: PlotDomain { y: Some(y_range), ..scene.domain }, ..scene };
+        let (mismatched, _) = diff_image(&render_scene(&fixed_scene), &render_builtin_scene(&scene, surface));
+        let mismatched_fraction = mismatched as f32 / (WIDTH * HEIGHT) as f32;
+        assert!(
+            mismatched_fraction <= 5. * MAX_MISMATCHED_FRACTION,
+            "{:?} differs from its expression in {} pixels",
+            surface,
+            mismatched
+        );
+    }
+}
+
+// Graph3D keeps its height buffers between frames and rewrites them in place on set_y_values
+#[test]
+fn reused_buffers_match_fresh_render() {
+    let scene = |expression, time| Scene {
+        expression,
+        time,
+        ..Scene::default()
+    };
+    let expected = render_scene(&scene(c::DEFAULT_SURFACE_EXPRESSION, 1000.));
+
+    let gl = SoftwareBackend::new(WIDTH, HEIGHT);
+    gl_setup::configure_context(&gl);
+    let mut graph = Graph3D::new(&gl).unwrap();
+    draw_scene(&gl, &graph, &scene(c::DEFAULT_SURFACE_EXPRESSION, 0.));
+    graph.set_normal_mode(cf::NormalMode::AreaWeighted);
+    draw_scene(&gl, &graph, &scene("0.2 * sin(x) * cos(z)", 0.));
+    graph.set_grid_size(&gl, cf::GridSize::new(40, 70).unwrap()).unwrap();
+    draw_scene(&gl, &graph, &scene("0.2 * sin(x) * cos(z)", 0.));
+    graph.set_grid_size(&gl, cf::GridSize::square(c::DEFAULT_GRID_SIZE).unwrap()).unwrap();
+    graph.set_normal_mode(cf::NormalMode::default());
+    draw_scene(&gl, &graph, &scene(c::DEFAULT_SURFACE_EXPRESSION, 0.));
+    draw_scene(&gl, &graph, &scene(c::DEFAULT_SURFACE_EXPRESSION, 1000.));
+    let actual = draw_scene(&gl, &graph, &scene(c::DEFAULT_SURFACE_EXPRESSION, 1000.));
+
+    let (mismatched, _) = diff_image(&expected, &actual);
+    assert_eq!(mismatched, 0);
+}
+
+// FNV-1a 64, see PortedSource
+fn source_hash(vertex: &str, fragment: &str) -> u64 {
+    vertex.bytes().chain(fragment.bytes()).fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[test]
+fn ported_shader_sources_are_unchanged() {
+    let mut changed = Vec::new();
+    for (index, ported) in PORTED_SOURCES.iter().enumerate() {
+        assert!(builtin_shader(ported.vertex, ported.fragment, ported.defines).is_some());
+        let vertex = preprocess(ported.vertex, ported.defines).unwrap();
+        let fragment = preprocess(ported.fragment, ported.defines).unwrap();
+        let hash = source_hash(&vertex, &fragment);
+        if hash != ported.hash {
+            changed.push(format!("PORTED_SOURCES[{}] {:?}: {:#018x}", index, ported.defines, hash));
+        }
+    }
+    assert!(
+        changed.is_empty(),
+        "GLSL with a software port changed, update the port in src/backend/software/shaders.rs, \
+         then its hash in PORTED_SOURCES and the goldens:\n{}",
+        changed.join("\n")
+    );
+}
+
+#[test]
+fn every_surface_style_has_a_pinned_port() {
+    use rust_3d_demo::shaders::{fragment as f, vertex as v};
+    for vertex in [v::graph_3d::SHADER, v::graph_3d_surface::SHADER] {
+        for (lighting, fragment) in [
+            (&[][..], f::varying_color_from_vertex::SHADER),
+            (&["PER_PIXEL_LIGHTING"][..], f::lit_surface::SHADER),
+            (&["PER_PIXEL_LIGHTING", "BLINN_PHONG"][..], f::lit_surface::SHADER),
+        ] {
+            for colormap in [&[][..], &["COLORMAP"][..]] {
+                for fog in [&[][..], &["FOG"][..]] {
+                    let defines = [colormap, fog, lighting].concat();
+                    assert!(
+                        PORTED_SOURCES
+                            .iter()
+                            .any(|ported| ported.vertex == vertex && ported.fragment == fragment && ported.defines == defines),
+                        "No pinned source for {:?}",
+                        defines
+                    );
+                }
+            }
+        }
+    }
+}
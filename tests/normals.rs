@@ -0,0 +1,89 @@
+// Vertex normals of the grid mesh in every NormalMode
+use nalgebra::Vector3;
+use rust_3d_demo::common_funcs::{self as cf, GridSize, NormalMode};
+
+const MODES: [NormalMode; 3] = [NormalMode::ForwardTriangle, NormalMode::CentralDifference, NormalMode::AreaWeighted];
+
+// Heights of `f` at the vertices, x and z in the display coordinates of the grid,
+// which runs over [0, 2] along both axes for the normals
+fn heights(grid_size: GridSize, f: impl Fn(f32, f32) -> f32) -> Vec<f32> {
+    let (step_x, step_z) = (2. / grid_size.x() as f32, 2. / grid_size.z() as f32);
+    (0..=grid_size.z())
+        .flat_map(|i| (0..=grid_size.x()).map(move |j| (j as f32 * step_x, i as f32 * step_z)))
+        .map(|(x, z)| f(x, z))
+        .collect()
+}
+
+fn normal_at(normals: &[f32], grid_size: GridSize, i: usize, j: usize) -> Vector3<f32> {
+    let start = (i * (grid_size.x() + 1) + j) * 3;
+    Vector3::new(normals[start], normals[start + 1], normals[start + 2])
+}
+
+fn assert_normal(actual: Vector3<f32>, expected: Vector3<f32>, context: &str) {
+    let expected = expected.normalize();
+    assert!((actual - expected).norm() < 1e-5, "{}: {:?} != {:?}", context, actual, expected);
+}
+
+#[test]
+fn tilted_plane_has_the_analytic_normal_everywhere() {
+    let grid_size = GridSize::new(5, 3).unwrap();
+    // y = 0.3 x - 0.7 z + 0.1
+    let y_vals = heights(grid_size, |x, z| 0.3 * x - 0.7 * z + 0.1);
+    let expected = Vector3::new(-0.3, 1., 0.7);
+    for mode in MODES {
+        let normals = cf::get_grid_normals(grid_size, &y_vals, mode);
+        assert_eq!(normals.len(), grid_size.vertex_count() * 3);
+        for i in 0..=grid_size.z() {
+            for j in 0..=grid_size.x() {
+                assert_normal(normal_at(&normals, grid_size, i, j), expected, &format!("{:?} at row {}, column {}", mode, i, j));
+            }
+        }
+    }
+}
+
+#[test]
+fn flat_surface_points_up() {
+    let grid_size = GridSize::new(2, 2).unwrap();
+    for mode in MODES {
+        for normal in cf::get_grid_normals(grid_size, &[0.4; 9], mode).chunks_exact(3) {
+            assert_eq!(normal, [0., 1., 0.], "{:?}", mode);
+        }
+    }
+}
+
+// y = x^2 along a single row of cells, where the three modes differ
+#[test]
+fn borders_use_one_sided_differences() {
+    let grid_size = GridSize::new(4, 1).unwrap();
+    let y_vals = heights(grid_size, |x, _| x * x);
+    // x is 0, 0.5, 1, 1.5, 2 and y is 0, 0.25, 1, 2.25, 4
+    let slope_normal = |slope: f32| Vector3::new(-slope, 1., 0.);
+
+    let central = cf::get_grid_normals(grid_size, &y_vals, NormalMode::CentralDifference);
+    // Forward difference at the first column, backward at the last, central in between
+    assert_normal(normal_at(&central, grid_size, 0, 0), slope_normal(0.25 / 0.5), "first column");
+    assert_normal(normal_at(&central, grid_size, 0, 2), slope_normal((2.25 - 0.25) / 1.), "middle column");
+    assert_normal(normal_at(&central, grid_size, 1, 4), slope_normal((4. - 2.25) / 0.5), "last column");
+
+    let forward = cf::get_grid_normals(grid_size, &y_vals, NormalMode::ForwardTriangle);
+    assert_normal(normal_at(&forward, grid_size, 0, 0), slope_normal(0.25 / 0.5), "first column");
+    assert_normal(normal_at(&forward, grid_size, 0, 2), slope_normal((2.25 - 1.) / 0.5), "middle column");
+    assert_normal(normal_at(&forward, grid_size, 1, 4), slope_normal((4. - 2.25) / 0.5), "last column");
+
+    // The corner vertices touch a single triangle, or two with the same normal
+    let area = cf::get_grid_normals(grid_size, &y_vals, NormalMode::AreaWeighted);
+    assert_normal(normal_at(&area, grid_size, 0, 0), slope_normal(0.25 / 0.5), "first column");
+    assert_normal(normal_at(&area, grid_size, 1, 4), slope_normal((4. - 2.25) / 0.5), "last column");
+}
+
+#[test]
+fn last_row_uses_the_row_above() {
+    // y = z^2 seen along z, the last row only has a row above it
+    let grid_size = GridSize::new(1, 4).unwrap();
+    let y_vals = heights(grid_size, |_, z| z * z);
+    for mode in MODES {
+        let normals = cf::get_grid_normals(grid_size, &y_vals, mode);
+        let last_row = normal_at(&normals, grid_size, 4, 1);
+        assert_normal(last_row, Vector3::new(0., 1., -(4. - 2.25) / 0.5), &format!("{:?}", mode));
+    }
+}
@@ -0,0 +1,66 @@
+// Mapping of data coordinates to the coordinates Graph3D draws in
+use rust_3d_demo::plot_domain::{AxisRange, PlotDomain};
+use rust_3d_demo::programs::BuiltinSurface;
+
+#[test]
+fn axis_range_needs_finite_increasing_bounds() {
+    assert!(AxisRange::new(-1., 1.).is_ok());
+    assert!(AxisRange::new(1., 1.).is_err());
+    assert!(AxisRange::new(2., 1.).is_err());
+    assert!(AxisRange::new(f32::NAN, 1.).is_err());
+    assert!(AxisRange::new(0., f32::INFINITY).is_err());
+}
+
+#[test]
+fn axis_range_needs_a_finite_width() {
+    // Both bounds are finite but max - min overflows
+    assert!(AxisRange::new(-3e38, 3e38).is_err());
+    assert!(AxisRange::new(f32::MIN, 0.).is_ok());
+    let range = AxisRange::new(-1.5e38, 1.5e38).unwrap();
+    assert_eq!(range.normalize(1.5e38), 1.);
+}
+
+#[test]
+fn enclosing_range_skips_non_finite_values() {
+    assert_eq!(AxisRange::enclosing(&[0.5, f32::NAN, -2., f32::INFINITY]), AxisRange::new(-2., 0.5).ok());
+    assert_eq!(AxisRange::enclosing(&[f32::NAN]), None);
+    assert_eq!(AxisRange::enclosing(&[-3e38, 3e38]), None);
+    // A flat surface stays in the middle
+    assert_eq!(AxisRange::enclosing(&[4., 4.]), AxisRange::new(3., 5.).ok());
+}
+
+#[test]
+fn default_y_range_fits_the_heights() {
+    let domain = PlotDomain::default();
+    assert_eq!(domain.y, None);
+    let mut y_vals = [0.15, -0.15, 0.];
+    domain.normalize_y(&mut y_vals);
+    assert_eq!(y_vals, [1., -1., 0.]);
+}
+
+#[test]
+fn fixed_y_range_maps_to_the_plot_height() {
+    let domain = PlotDomain { y: Some(AxisRange::new(0., 10.).unwrap()), ..PlotDomain::default() };
+    let mut y_vals = [0., 2.5, 10., 20.];
+    domain.normalize_y(&mut y_vals);
+    assert_eq!(y_vals, [-1., -0.5, 1., 3.]);
+}
+
+#[test]
+fn builtin_surfaces_fit_the_bounds_of_their_function() {
+    let domain = PlotDomain::default();
+    assert_eq!(BuiltinSurface::Ripple.y_range(&domain), Some(AxisRange::new(-0.15, 0.15).unwrap()));
+    assert_eq!(BuiltinSurface::Waves.y_range(&domain), Some(AxisRange::new(-0.2, 0.2).unwrap()));
+    // 0.02 (x^2 - z^2), x^2 in [1, 4] and z^2 in [0, 9]
+    let domain = PlotDomain {
+        x: AxisRange::new(1., 2.).unwrap(),
+        z: AxisRange::new(-1., 3.).unwrap(),
+        y: None,
+    };
+    let y_range = BuiltinSurface::Saddle.y_range(&domain).unwrap();
+    assert!((y_range.min + 0.16).abs() < 1e-6 && (y_range.max - 0.08).abs() < 1e-6, "{:?}", y_range);
+
+    let fixed = AxisRange::new(-1., 1.).unwrap();
+    let domain = PlotDomain { y: Some(fixed), ..domain };
+    assert_eq!(BuiltinSurface::Saddle.y_range(&domain), Some(fixed));
+}
@@ -0,0 +1,89 @@
+// #include and #define handling of shaders::preprocessor
+use rust_3d_demo::backend::software::SoftwareBackend;
+use rust_3d_demo::backend::RenderBackend;
+use rust_3d_demo::error::Error;
+use rust_3d_demo::shaders::preprocessor::preprocess;
+use rust_3d_demo::shaders::{fragment, include, vertex};
+
+#[test]
+fn defines_go_after_the_version() {
+    let source = "\n#version 100\nvoid main() {}\n";
+    assert_eq!(
+        preprocess(source, &["FOG", "STEPS 4"]).unwrap(),
+        "\n#version 100\n#define FOG\n#define STEPS 4\nvoid main() {}\n"
+    );
+    assert_eq!(preprocess("void main() {}", &["FOG"]).unwrap(), "#define FOG\nvoid main() {}\n");
+}
+
+#[test]
+fn includes_are_expanded_once() {
+    let source = "    #include \"lighting\"\n#include <lighting>\nvoid main() {}";
+    let expanded = preprocess(source, &[]).unwrap();
+    assert_eq!(expanded, format!("{}void main() {{}}\n", include::lookup("lighting").unwrap()));
+    assert_eq!(expanded.matches("vec3 lighting(vec3 normal)").count(), 1);
+}
+
+#[test]
+fn includes_are_expanded_once_per_active_branch() {
+    let source = "#ifdef FOG\n#include \"fog\"\n#else\n#include \"fog\"\n#include \"colormap\"\n#endif\n#include \"fog\"\n";
+    let fog = include::lookup("fog").unwrap();
+    let colormap = include::lookup("colormap").unwrap();
+    assert_eq!(
+        preprocess(source, &["FOG"]).unwrap(),
+        format!("#define FOG\n#ifdef FOG\n{}#else\n#endif\n", fog)
+    );
+    assert_eq!(preprocess(source, &[]).unwrap(), format!("#ifdef FOG\n#else\n{}{}#endif\n", fog, colormap));
+}
+
+#[test]
+fn nested_conditionals_and_source_defines_are_followed() {
+    let source = "\
+#define SHADOWS
+#ifndef FOG
+#ifdef SHADOWS
+#include \"colormap\"
+#endif
+#undef SHADOWS
+#ifdef SHADOWS
+#include \"fog\"
+#endif
+#endif
+";
+    let expanded = preprocess(source, &[]).unwrap();
+    assert!(expanded.contains(include::lookup("colormap").unwrap()));
+    assert!(!expanded.contains(include::lookup("fog").unwrap()));
+    let expanded = preprocess(source, &["FOG"]).unwrap();
+    assert!(!expanded.contains(include::lookup("colormap").unwrap()));
+}
+
+#[test]
+fn unbalanced_and_unsupported_conditionals_are_errors() {
+    for source in ["#ifdef FOG\n", "#endif\n", "#ifdef FOG\n#else\n#else\n#endif\n", "#if FOG\n#endif\n", "#ifdef\n#endif\n"] {
+        assert!(matches!(preprocess(source, &[]), Err(Error::Preprocess(_))), "{:?}", source);
+    }
+}
+
+#[test]
+fn unknown_and_malformed_includes_are_errors() {
+    assert_eq!(
+        preprocess("#include \"shadows\"", &[]),
+        Err(Error::Preprocess(String::from("Unknown shader include \"shadows\""))),
+    );
+    assert_eq!(
+        preprocess("#include lighting", &[]),
+        Err(Error::Preprocess(String::from("Expected #include \"<name>\", got '#include lighting'"))),
+    );
+}
+
+#[test]
+fn invalid_defines_are_errors() {
+    assert!(matches!(preprocess("void main() {}", &["FOG;"]), Err(Error::Preprocess(_))));
+    assert!(matches!(preprocess("void main() {}", &[""]), Err(Error::Preprocess(_))));
+}
+
+#[test]
+fn link_program_reports_preprocessing_errors() {
+    let gl = SoftwareBackend::new(4, 4);
+    let result = gl.link_program(vertex::graph_3d::SHADER, fragment::varying_color_from_vertex::SHADER, &["FOG;"], &[]);
+    assert!(matches!(result, Err(Error::Preprocess(_))));
+}
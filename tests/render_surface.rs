@@ -0,0 +1,71 @@
+// Argument errors of the render_surface command line tool
+use std::process::Command;
+
+// Runs the tool and returns its stderr, asserting it failed before writing anything
+fn run_failing(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_render_surface"))
+        .args(["-o", "/nonexistent/never-written.png"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(!output.status.success(), "{:?} succeeded", args);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Usage: render_surface"), "{:?} did not print the usage:\n{}", args, stderr);
+    stderr
+}
+
+fn assert_error(args: &[&str], message: &str) {
+    let stderr = run_failing(args);
+    assert!(stderr.starts_with(&format!("error: {}\n", message)), "{:?} printed:\n{}", args, stderr);
+}
+
+#[test]
+fn help_succeeds() {
+    let output = Command::new(env!("CARGO_BIN_EXE_render_surface")).arg("--help").output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().starts_with("Usage: render_surface"));
+}
+
+#[test]
+fn image_size_is_bounded() {
+    assert_error(&["--width", "0"], "--width and --height must be positive");
+    assert_error(&["--height", "0"], "--width and --height must be positive");
+    assert_error(&["--width", "100000", "--height", "100000"], "--width and --height must be at most 8192");
+    assert_error(&["--height", "8193"], "--width and --height must be at most 8192");
+    assert_error(&["--width", "-5"], "Invalid value '-5' for --width");
+}
+
+#[test]
+fn missing_and_invalid_values_are_reported() {
+    assert_error(&["--width"], "Missing value for --width");
+    assert_error(&["--grid", "many"], "Invalid value 'many' for --grid");
+    assert_error(&["--normals", "sideways"], "Invalid value 'sideways' for --normals");
+    assert_error(&["--x-range", "1"], "Invalid value '1' for --x-range, expected MIN,MAX");
+    assert_error(&["--light-direction", "1,2"], "Invalid value '1,2' for --light-direction, expected X,Y,Z");
+}
+
+#[test]
+fn unknown_and_conflicting_arguments_are_reported() {
+    assert_error(&["--colour"], "Unknown option --colour");
+    assert_error(&["x", "z"], "Unexpected argument 'z'");
+    assert_error(&["x", "--builtin", "ripple"], "Give either an EXPRESSION or --builtin, not both");
+}
+
+#[test]
+fn builtin_surface_renders_with_the_automatic_y_range() {
+    let output = std::env::temp_dir().join(format!("render_surface_builtin_{}.png", std::process::id()));
+    let status = Command::new(env!("CARGO_BIN_EXE_render_surface"))
+        .args(["--builtin", "saddle", "--width", "64", "--height", "48", "-o"])
+        .arg(&output)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(std::fs::metadata(&output).unwrap().len() > 0);
+    std::fs::remove_file(&output).unwrap();
+}
+
+#[test]
+fn invalid_ranges_are_reported() {
+    let stderr = run_failing(&["--x-range", "2,1"]);
+    assert!(stderr.starts_with("error: --x-range: "), "{}", stderr);
+}
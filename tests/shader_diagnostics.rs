@@ -0,0 +1,72 @@
+// Parsing of shader info logs from different drivers, and the annotated output
+use rust_3d_demo::shader_diagnostics::{annotate, parse_info_log, Diagnostic, Severity};
+
+const SOURCE: &str = "
+    attribute vec4 aPosition;
+    void main() {
+        gl_Position = aPositon;
+    }
+";
+
+#[test]
+fn parses_angle_logs() {
+    let diagnostics = parse_info_log(
+        "ERROR: 0:4: 'aPositon' : undeclared identifier\nWARNING: 0:2: 'aPosition' : unused\n\0",
+    );
+    assert_eq!(diagnostics, vec![
+        Diagnostic {
+            severity: Severity::Error,
+            line: Some(4),
+            column: None,
+            message: String::from("'aPositon' : undeclared identifier"),
+        },
+        Diagnostic {
+            severity: Severity::Warning,
+            line: Some(2),
+            column: None,
+            message: String::from("'aPosition' : unused"),
+        },
+    ]);
+}
+
+#[test]
+fn parses_mesa_logs_with_columns() {
+    let diagnostics = parse_info_log("0:4(23): error: `aPositon' undeclared\n");
+    assert_eq!(diagnostics, vec![Diagnostic {
+        severity: Severity::Error,
+        line: Some(4),
+        column: Some(23),
+        message: String::from("`aPositon' undeclared"),
+    }]);
+}
+
+#[test]
+fn keeps_lines_without_a_location() {
+    let diagnostics = parse_info_log("ERROR: 1 compilation errors.  No code generated.");
+    assert_eq!(diagnostics, vec![Diagnostic {
+        severity: Severity::Error,
+        line: None,
+        column: None,
+        message: String::from("1 compilation errors.  No code generated."),
+    }]);
+}
+
+#[test]
+fn points_at_the_reported_column() {
+    let annotated = annotate(SOURCE, &parse_info_log("0:4(23): error: `aPositon' undeclared"));
+    assert_eq!(
+        annotated,
+        "error: `aPositon' undeclared\n\
+         \x20 --> line 4, column 23\n\
+         \x20 |\n\
+         3 |     void main() {\n\
+         4 |         gl_Position = aPositon;\n\
+         \x20 |                       ^"
+    );
+}
+
+#[test]
+fn ignores_lines_outside_the_source() {
+    let annotated = annotate(SOURCE, &parse_info_log("ERROR: 0:40: 'x' : syntax error"));
+    assert_eq!(annotated, "error: 'x' : syntax error");
+}
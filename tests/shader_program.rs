@@ -0,0 +1,83 @@
+// ShaderProgram reflection and warnings, with the software backend
+use web_sys::WebGlRenderingContext as GL;
+use rust_3d_demo::backend::buffers::VertexBuffer;
+use rust_3d_demo::backend::program::ShaderProgram;
+use rust_3d_demo::backend::software::SoftwareBackend;
+use rust_3d_demo::backend::RenderBackend;
+use rust_3d_demo::error::Error;
+use rust_3d_demo::shaders::{fragment, vertex};
+
+fn gradient_program(gl: &SoftwareBackend, attributes: &[&str]) -> ShaderProgram<SoftwareBackend> {
+    ShaderProgram::new(gl, vertex::color_2d_gradient::SHADER, fragment::varying_color_from_vertex::SHADER, &[], attributes).unwrap()
+}
+
+#[test]
+fn lists_active_uniforms_with_their_types() {
+    let gl = SoftwareBackend::new(4, 4);
+    let program = gradient_program(&gl, &["aPosition", "aColor"]);
+    let mut uniforms: Vec<(String, u32)> = program.active_uniforms().map(|uniform| (uniform.name.clone(), uniform.type_)).collect();
+    uniforms.sort();
+    assert_eq!(uniforms, vec![
+        (String::from("uOpacity"), GL::FLOAT),
+        (String::from("uTransform"), GL::FLOAT_MAT4),
+    ]);
+    assert!(program.warnings().is_empty());
+}
+
+#[test]
+fn attributes_get_the_locations_they_are_listed_in() {
+    let gl = SoftwareBackend::new(4, 4);
+    let program = gradient_program(&gl, &["aColor", "aPosition"]);
+    assert_eq!(program.attribute_location("aColor"), Ok(0));
+    assert_eq!(program.attribute_location("aPosition"), Ok(1));
+    assert_eq!(program.attribute_location("aNormal"), Err(Error::MissingAttribute(String::from("aNormal"))));
+}
+
+#[test]
+fn draws_the_same_whatever_the_attribute_locations() {
+    let render = |attributes: &[&str]| {
+        let gl = SoftwareBackend::new(4, 4);
+        let program = gradient_program(&gl, attributes);
+        program.use_program(&gl);
+        let positions = VertexBuffer::with_data(&gl, 2, &[-1., -1., 3., -1., -1., 3.], GL::STATIC_DRAW).unwrap();
+        let colors = VertexBuffer::with_data(&gl, 4, &[1., 0., 0., 1., 0., 1., 0., 1., 0., 0., 1., 1.], GL::STATIC_DRAW).unwrap();
+        positions.bind_attribute(&gl, program.attribute_location("aPosition").unwrap(), 0);
+        colors.bind_attribute(&gl, program.attribute_location("aColor").unwrap(), 0);
+        program.set_float(&gl, "uOpacity", 1.);
+        let mut identity = [0.; 16];
+        identity[0] = 1.;
+        identity[5] = 1.;
+        identity[10] = 1.;
+        identity[15] = 1.;
+        program.set_mat4(&gl, "uTransform", &identity);
+        gl.draw_arrays(GL::TRIANGLES, 0, 3);
+        gl.read_pixels()
+    };
+    let pixels = render(&["aPosition", "aColor"]);
+    assert!(pixels.chunks(4).any(|pixel| pixel[0] > 0 && pixel[1] > 0 && pixel[2] > 0));
+    assert_eq!(pixels, render(&["aColor", "aPosition"]));
+}
+
+#[test]
+fn warns_once_about_mismatched_uniforms() {
+    let gl = SoftwareBackend::new(4, 4);
+    let program = gradient_program(&gl, &["aPosition", "aColor"]);
+    for _ in 0..3 {
+        program.set_vec2(&gl, "uOpacity", 1., 1.);
+        program.set_float(&gl, "uOpasity", 1.);
+    }
+    assert_eq!(program.warnings(), vec![
+        String::from("Uniform 'uOpacity' is a float in the shaders but was set as a vec2"),
+        String::from("Uniform 'uOpasity' is not used by the shaders"),
+    ]);
+}
+
+#[test]
+fn warns_about_attributes_without_a_location() {
+    let gl = SoftwareBackend::new(4, 4);
+    let program = gradient_program(&gl, &["aPosition", "aNormal"]);
+    assert_eq!(program.warnings(), vec![
+        String::from("Attribute 'aNormal' is not used by the vertex shader"),
+        String::from("Attribute 'aColor' was not given a location, nothing feeds it"),
+    ]);
+}
@@ -0,0 +1,498 @@
+// Native validation of the GLSL ES 1.00 strings in rust_3d_demo::shaders, which would
+// otherwise only be compiled by the browser.
+// Sources are preprocessed like the backends do, with every combination of DEFINES.
+// naga only parses desktop GLSL 4.x, so each source is first rewritten line by line to
+// `#version 450 core`: attributes and varyings get locations, uniforms get bindings and
+// gl_FragColor becomes an output. Line numbers are kept, so errors point at the original
+// lines. The programs then draw a frame with ValidatingBackend, which links with naga and,
+// like a driver, only reports the uniforms and attributes in use
+use std::cell::RefCell;
+use std::path::Path;
+use nalgebra::UnitQuaternion;
+use naga::front::glsl::{Frontend, Options};
+use naga::valid::{Capabilities, GlobalUse, ModuleInfo, ValidationFlags, Validator};
+use web_sys::WebGlRenderingContext as GL;
+use rust_3d_demo::backend::{ActiveVariable, RenderBackend};
+use rust_3d_demo::common_funcs as cf;
+use rust_3d_demo::error::{Error, ShaderStage};
+use rust_3d_demo::plot_domain::{AxisRange, PlotDomain};
+use rust_3d_demo::programs::{BuiltinSurface, Color2D, Color2DGradient, Graph3D, LightingModel, SurfaceStyle};
+use rust_3d_demo::shader_diagnostics::{Diagnostic, Severity};
+use rust_3d_demo::shaders::preprocessor::preprocess;
+use rust_3d_demo::shaders::{fragment, include, vertex};
+
+const VERTEX_SHADERS: &[(&str, &str)] = &[
+    ("color_2d", vertex::color_2d::SHADER),
+    ("color_2d_gradient", vertex::color_2d_gradient::SHADER),
+    ("graph_3d", vertex::graph_3d::SHADER),
+    ("graph_3d_surface", vertex::graph_3d_surface::SHADER),
+];
+
+const FRAGMENT_SHADERS: &[(&str, &str)] = &[
+    ("color_2d", fragment::color_2d::SHADER),
+    ("lit_surface", fragment::lit_surface::SHADER),
+    ("varying_color_from_vertex", fragment::varying_color_from_vertex::SHADER),
+];
+
+// The #ifdef features of the shaders, see programs::SurfaceStyle
+const DEFINES: &[&[&str]] = &[
+    &[],
+    &["COLORMAP"],
+    &["FOG"],
+    &["COLORMAP", "FOG"],
+    &["PER_PIXEL_LIGHTING"],
+    &["PER_PIXEL_LIGHTING", "BLINN_PHONG", "COLORMAP", "FOG"],
+];
+
+// Same length as gl_FragColor, so columns do not move
+const FRAG_COLOR: &str = "outFragColor";
+
+struct Varying {
+    name: String,
+    type_name: String,
+}
+
+// A shader rewritten for naga
+struct Translated {
+    source: String,
+    // Per line, the column after the rewritten qualifier and how many bytes it grew by
+    column_shifts: Vec<(usize, usize)>,
+    varyings: Vec<Varying>,
+}
+
+impl Translated {
+    // Column in the original source for a column in the translated one
+    fn original_column(&self, line: usize, column: usize) -> usize {
+        match self.column_shifts.get(line - 1) {
+            Some(&(end, shift)) if shift > 0 && column > end => column - shift,
+            Some(&(end, shift)) if shift > 0 => end + 1 - shift,
+            _ => column,
+        }
+    }
+}
+
+fn error_at(line: Option<usize>, message: String) -> Diagnostic {
+    Diagnostic { severity: Severity::Error, line, column: None, message }
+}
+
+fn translate(source: &str, stage: ShaderStage) -> Result<Translated, Vec<Diagnostic>> {
+    let mut errors = Vec::new();
+    let mut return_var = Translated {
+        source: String::new(),
+        column_shifts: Vec::new(),
+        varyings: Vec::new(),
+    };
+    let (mut attributes, mut varyings, mut uniforms) = (0, 0, 0);
+    let mut has_float_precision = false;
+
+    for (i, line) in source.lines().enumerate() {
+        let number = i + 1;
+        let indent = line.len() - line.trim_start().len();
+        let text = line.trim_start();
+        if number == 1 {
+            if !text.is_empty() {
+                errors.push(error_at(Some(1), String::from(
+                    "Shader strings start with a newline, line 1 is replaced with the #version naga needs",
+                )));
+            }
+            return_var.source.push_str("#version 450 core\n");
+            return_var.column_shifts.push((0, 0));
+            continue;
+        }
+
+        let keyword = text.split_whitespace().next().unwrap_or("");
+        let qualifier = match (keyword, stage) {
+            ("attribute", ShaderStage::Vertex) => {
+                attributes += 1;
+                Some(format!("layout(location = {}) in", attributes - 1))
+            }
+            ("varying", _) => {
+                let mut types = text
+                    .trim_end_matches(';')
+                    .split_whitespace()
+                    .skip(1)
+                    .filter(|word| !matches!(*word, "lowp" | "mediump" | "highp"));
+                if let (Some(type_name), Some(name)) = (types.next(), types.next()) {
+                    return_var.varyings.push(Varying { name: name.to_string(), type_name: type_name.to_string() });
+                }
+                varyings += 1;
+                let direction = if stage == ShaderStage::Vertex { "out" } else { "in" };
+                Some(format!("layout(location = {}) {}", varyings - 1, direction))
+            }
+            ("uniform", _) => {
+                uniforms += 1;
+                Some(format!("layout(binding = {}) uniform", uniforms - 1))
+            }
+            ("attribute", ShaderStage::Fragment) | ("in", _) | ("out", _) | ("layout", _) => {
+                errors.push(error_at(Some(number), format!("'{}' is not a GLSL ES 1.00 {} shader qualifier", keyword, stage)));
+                None
+            }
+            ("#version", _) if text != "#version 100" => {
+                errors.push(error_at(Some(number), format!("'{}' is not GLSL ES 1.00", text)));
+                None
+            }
+            _ => None,
+        };
+
+        let mut translated = match &qualifier {
+            Some(qualifier) => {
+                return_var.column_shifts.push((indent + qualifier.len(), qualifier.len() - keyword.len()));
+                format!("{}{}{}", &line[..indent], qualifier, &text[keyword.len()..])
+            }
+            None => {
+                return_var.column_shifts.push((0, 0));
+                line.to_string()
+            }
+        };
+        if stage == ShaderStage::Fragment {
+            translated = translated.replace("gl_FragColor", FRAG_COLOR);
+            if keyword == "precision" && text.trim_end_matches(';').ends_with(" float") {
+                has_float_precision = true;
+                translated.push_str(&format!(" layout(location = 0) out vec4 {};", FRAG_COLOR));
+            }
+        }
+        return_var.source.push_str(&translated);
+        return_var.source.push('\n');
+    }
+
+    if stage == ShaderStage::Fragment && !has_float_precision {
+        errors.push(error_at(None, String::from(
+            "Fragment shaders need a default float precision, e.g. 'precision mediump float;'",
+        )));
+    }
+    if errors.is_empty() { Ok(return_var) } else { Err(errors) }
+}
+
+struct Compiled {
+    module: naga::Module,
+    info: ModuleInfo,
+    varyings: Vec<Varying>,
+}
+
+impl Compiled {
+    fn uses(&self, name: &str, usage: GlobalUse) -> bool {
+        self.module
+            .global_variables
+            .iter()
+            .any(|(handle, global)| global.name.as_deref() == Some(name) && self.info.get_entry_point(0)[handle].contains(usage))
+    }
+
+    fn active_variable(&self, name: &Option<String>, ty: naga::Handle<naga::Type>) -> Option<ActiveVariable> {
+        Some(ActiveVariable { name: name.clone()?, type_: gl_type(&self.module.types[ty].inner), size: 1 })
+    }
+
+    // Uniforms read by main
+    fn active_uniforms(&self) -> Vec<ActiveVariable> {
+        self.module
+            .global_variables
+            .iter()
+            .filter(|(handle, global)| {
+                global.space == naga::AddressSpace::Uniform && !self.info.get_entry_point(0)[*handle].is_empty()
+            })
+            .filter_map(|(_, global)| self.active_variable(&global.name, global.ty))
+            .collect()
+    }
+
+    // Inputs read by main, naga copies every input into a global of the same name
+    fn active_inputs(&self) -> Vec<ActiveVariable> {
+        self.module.entry_points[0]
+            .function
+            .arguments
+            .iter()
+            .filter_map(|argument| self.active_variable(&argument.name, argument.ty))
+            .filter(|input| self.uses(&input.name, GlobalUse::READ))
+            .collect()
+    }
+}
+
+fn gl_type(inner: &naga::TypeInner) -> u32 {
+    use naga::{TypeInner, VectorSize};
+    match inner {
+        TypeInner::Scalar(_) => GL::FLOAT,
+        TypeInner::Vector { size: VectorSize::Bi, .. } => GL::FLOAT_VEC2,
+        TypeInner::Vector { size: VectorSize::Tri, .. } => GL::FLOAT_VEC3,
+        TypeInner::Vector { size: VectorSize::Quad, .. } => GL::FLOAT_VEC4,
+        TypeInner::Matrix { columns: VectorSize::Bi, .. } => GL::FLOAT_MAT2,
+        TypeInner::Matrix { columns: VectorSize::Tri, .. } => GL::FLOAT_MAT3,
+        TypeInner::Matrix { columns: VectorSize::Quad, .. } => GL::FLOAT_MAT4,
+        // Not used by the shaders
+        _ => 0,
+    }
+}
+
+// Diagnostic for a naga error at `span` of the translated source
+fn naga_diagnostic(translated: &Translated, span: Option<naga::Span>, message: String) -> Diagnostic {
+    let location = span.filter(|span| span.is_defined()).map(|span| span.location(&translated.source));
+    let line = location.map(|location| location.line_number as usize);
+    Diagnostic {
+        severity: Severity::Error,
+        line,
+        column: location.map(|location| translated.original_column(location.line_number as usize, location.line_position as usize)),
+        message,
+    }
+}
+
+fn compile(source: &str, stage: ShaderStage) -> Result<Compiled, Error> {
+    let compile_error = |diagnostics: Vec<Diagnostic>| {
+        let log = diagnostics.iter().map(|diagnostic| diagnostic.message.clone()).collect::<Vec<_>>().join("\n");
+        Error::ShaderCompile { stage, log, diagnostics, source: source.to_string() }
+    };
+    let translated = translate(source, stage).map_err(compile_error)?;
+    let naga_stage = match stage {
+        ShaderStage::Vertex => naga::ShaderStage::Vertex,
+        ShaderStage::Fragment => naga::ShaderStage::Fragment,
+    };
+
+    let module = Frontend::default()
+        .parse(&Options::from(naga_stage), &translated.source)
+        .map_err(|errors| {
+            compile_error(errors.errors
+                .iter()
+                .map(|error| naga_diagnostic(&translated, Some(error.meta), error.kind.to_string()))
+                .collect())
+        })?;
+    let info = Validator::new(ValidationFlags::all(), Capabilities::empty())
+        .validate(&module)
+        .map_err(|error| {
+            // The top level only names the function, the details are in the sources
+            let mut message = error.as_inner().to_string();
+            let mut cause = std::error::Error::source(error.as_inner());
+            while let Some(inner) = cause {
+                message.push_str(&format!(": {}", inner));
+                cause = inner.source();
+            }
+            // The last span is the innermost, e.g. the expression rather than the function
+            let span = error.spans().last().map(|(span, _)| *span);
+            compile_error(vec![naga_diagnostic(&translated, span, message)])
+        })?;
+    Ok(Compiled { module, info, varyings: translated.varyings })
+}
+
+struct LinkedProgram {
+    uniforms: Vec<ActiveVariable>,
+    attributes: Vec<ActiveVariable>,
+    bound_attributes: Vec<String>,
+}
+
+// Links with naga and records the active uniforms and attributes, everything else is a no-op
+#[derive(Default)]
+struct ValidatingBackend {
+    programs: RefCell<Vec<LinkedProgram>>,
+}
+
+impl RenderBackend for ValidatingBackend {
+    type Program = usize;
+    type Buffer = ();
+    type UniformLocation = ();
+
+    fn link_program(&self, vert_source: &str, frag_source: &str, defines: &[&str], attributes: &[&str]) -> Result<usize, Error> {
+        let vertex = compile(&preprocess(vert_source, defines)?, ShaderStage::Vertex)?;
+        let fragment = compile(&preprocess(frag_source, defines)?, ShaderStage::Fragment)?;
+        for varying in &fragment.varyings {
+            match vertex.varyings.iter().find(|declared| declared.name == varying.name) {
+                Some(declared) if declared.type_name == varying.type_name => {}
+                Some(declared) => {
+                    return Err(Error::Link(format!(
+                        "Varying '{}' is a {} in the vertex shader and a {} in the fragment shader",
+                        varying.name, declared.type_name, varying.type_name
+                    )));
+                }
+                None => return Err(Error::Link(format!("Varying '{}' is not written by the vertex shader", varying.name))),
+            }
+        }
+
+        let mut uniforms = vertex.active_uniforms();
+        uniforms.extend(fragment.active_uniforms());
+        let mut programs = self.programs.borrow_mut();
+        programs.push(LinkedProgram {
+            uniforms,
+            attributes: vertex.active_inputs(),
+            bound_attributes: attributes.iter().map(|name| name.to_string()).collect(),
+        });
+        Ok(programs.len() - 1)
+    }
+
+    fn use_program(&self, _program: Option<&usize>) {}
+
+    fn get_uniform_location(&self, program: &usize, name: &str) -> Option<()> {
+        self.programs.borrow()[*program].uniforms.iter().any(|uniform| uniform.name == name).then_some(())
+    }
+
+    // The bound location of active attributes, unbound ones are not needed by the tests
+    fn get_attrib_location(&self, program: &usize, name: &str) -> Option<u32> {
+        let programs = self.programs.borrow();
+        let program = &programs[*program];
+        if !program.attributes.iter().any(|attribute| attribute.name == name) {
+            return None;
+        }
+        program.bound_attributes.iter().position(|bound| bound == name).map(|location| location as u32)
+    }
+
+    fn active_attributes(&self, program: &usize) -> Vec<ActiveVariable> {
+        self.programs.borrow()[*program].attributes.clone()
+    }
+
+    fn active_uniforms(&self, program: &usize) -> Vec<ActiveVariable> {
+        self.programs.borrow()[*program].uniforms.clone()
+    }
+
+    fn create_buffer(&self) -> Option<()> {
+        Some(())
+    }
+    fn bind_buffer(&self, _target: u32, _buffer: Option<&()>) {}
+    fn buffer_data_f32(&self, _target: u32, _data: &[f32], _usage: u32) {}
+    fn buffer_data_u16(&self, _target: u32, _data: &[u16], _usage: u32) {}
+    fn buffer_data_u32(&self, _target: u32, _data: &[u32], _usage: u32) {}
+    fn buffer_sub_data_f32(&self, _target: u32, _offset: i32, _data: &[f32]) {}
+    fn buffer_sub_data_u16(&self, _target: u32, _offset: i32, _data: &[u16]) {}
+    fn buffer_sub_data_u32(&self, _target: u32, _offset: i32, _data: &[u32]) {}
+    fn vertex_attrib_pointer(&self, _index: u32, _size: i32, _type_: u32, _normalized: bool, _stride: i32, _offset: i32) {}
+    fn enable_vertex_attrib_array(&self, _index: u32) {}
+    fn uniform1f(&self, _location: Option<&()>, _x: f32) {}
+    fn uniform2f(&self, _location: Option<&()>, _x: f32, _y: f32) {}
+    fn uniform3f(&self, _location: Option<&()>, _x: f32, _y: f32, _z: f32) {}
+    fn uniform4f(&self, _location: Option<&()>, _x: f32, _y: f32, _z: f32, _w: f32) {}
+    fn uniform_matrix4fv(&self, _location: Option<&()>, _transpose: bool, _data: &[f32]) {}
+    fn draw_arrays(&self, _mode: u32, _first: i32, _count: i32) {}
+    fn draw_elements(&self, _mode: u32, _count: i32, _type_: u32, _offset: i32) {}
+    fn enable_element_index_uint(&self) -> bool {
+        true
+    }
+    fn enable(&self, _cap: u32) {}
+    fn blend_func(&self, _sfactor: u32, _dfactor: u32) {}
+    fn clear_color(&self, _red: f32, _green: f32, _blue: f32, _alpha: f32) {}
+    fn clear_depth(&self, _depth: f32) {}
+    fn clear(&self, _mask: u32) {}
+}
+
+#[test]
+fn every_shader_validates() {
+    let shaders = VERTEX_SHADERS
+        .iter()
+        .map(|(name, source)| (*name, *source, ShaderStage::Vertex))
+        .chain(FRAGMENT_SHADERS.iter().map(|(name, source)| (*name, *source, ShaderStage::Fragment)));
+    let failures: Vec<String> = shaders
+        .flat_map(|shader| DEFINES.iter().map(move |defines| (shader, *defines)))
+        .filter_map(|((name, source, stage), defines)| {
+            preprocess(source, defines)
+                .and_then(|source| compile(&source, stage))
+                .err()
+                .map(|error| format!("shaders::{}::{} with {:?}: {}", stage, name, defines, error))
+        })
+        .collect();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
+
+#[test]
+fn every_shader_module_is_validated() {
+    for (directory, listed) in [("vertex", VERTEX_SHADERS), ("fragment", FRAGMENT_SHADERS)] {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/shaders").join(directory);
+        for entry in std::fs::read_dir(&path).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_stem().unwrap().to_str().unwrap();
+            if name != "mod" {
+                assert!(
+                    listed.iter().any(|(listed_name, _)| *listed_name == name),
+                    "shaders::{}::{} is missing from this test's shader lists", directory, name
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn every_include_can_be_looked_up() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/shaders/include");
+    for entry in std::fs::read_dir(&path).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        if name != "mod" {
+            assert!(include::lookup(name).is_some(), "shaders::include::{} is missing from include::lookup", name);
+        }
+    }
+}
+
+// Draws a frame with every program, which warns about any uniform or attribute name or
+// type the shaders do not have
+#[test]
+fn programs_match_their_uniforms_and_attributes() {
+    let gl = ValidatingBackend::default();
+    let color_2d = Color2D::new(&gl).unwrap_or_else(|e| panic!("Color2D: {}", e));
+    color_2d.render(&gl, 0., 1., 0., 1., 1., 1.);
+    assert_eq!(color_2d.shader_warnings(), Vec::<String>::new());
+
+    let color_2d_gradient = Color2DGradient::new(&gl).unwrap_or_else(|e| panic!("Color2DGradient: {}", e));
+    color_2d_gradient.render(&gl, 0., 1., 0., 1., 1., 1.);
+    assert_eq!(color_2d_gradient.shader_warnings(), Vec::<String>::new());
+
+    let styles = [
+        SurfaceStyle::default(),
+        SurfaceStyle { colormap: true, fog: Some([0., 0., 0.]), lighting: LightingModel::Lambert },
+        SurfaceStyle { colormap: false, fog: None, lighting: LightingModel::Phong },
+        SurfaceStyle { colormap: true, fog: Some([0., 0., 0.]), lighting: LightingModel::BlinnPhong },
+    ];
+    for style in styles {
+        let mut graph_3d = Graph3D::with_grid_size(&gl, cf::GridSize::new(2, 2).unwrap()).unwrap_or_else(|e| panic!("Graph3D: {}", e));
+        graph_3d.set_style(&gl, style).unwrap_or_else(|e| panic!("Graph3D with {:?}: {}", style, e));
+        let rotation = UnitQuaternion::identity();
+        graph_3d.set_y_values(&gl, &[0.; 9]).unwrap();
+        graph_3d.render(&gl, 0., 1., 0., 1., 1., 1., &rotation, 1., 0., 0., 0.).unwrap();
+        let domain = PlotDomain::default();
+        let y_range = AxisRange::new(-1., 1.).unwrap();
+        graph_3d.render_builtin(&gl, 0., 1., 0., 1., 1., 1., &rotation, 1., 0., 0., 0., BuiltinSurface::Ripple, 0., &domain, y_range);
+        assert_eq!(graph_3d.shader_warnings(), Vec::<String>::new(), "with {:?}", style);
+    }
+}
+
+#[test]
+fn typo_is_reported_on_its_line() {
+    let source = "
+    precision mediump float;
+    varying lowp vec4 vColor;
+    void main() {
+        gl_FragColor = vColour;
+    }
+";
+    let Err(Error::ShaderCompile { diagnostics, .. }) = compile(source, ShaderStage::Fragment) else {
+        panic!("the typo compiled");
+    };
+    assert_eq!(diagnostics[0].line, Some(5));
+}
+
+#[test]
+fn glsl_es_3_qualifiers_are_rejected() {
+    let source = "
+    in vec4 aPosition;
+    void main() {
+        gl_Position = aPosition;
+    }
+";
+    let Err(Error::ShaderCompile { diagnostics, .. }) = compile(source, ShaderStage::Vertex) else {
+        panic!("'in' was accepted");
+    };
+    assert_eq!(diagnostics[0].line, Some(2));
+}
+
+#[test]
+fn unused_uniforms_and_attributes_have_no_location() {
+    let gl = ValidatingBackend::default();
+    let program = gl.link_program("
+    attribute vec4 aPosition;
+    attribute vec4 aUnused;
+    uniform float uUnused;
+    void main() {
+        gl_Position = aPosition;
+    }
+", fragment::color_2d::SHADER, &[], &["aPosition", "aUnused"]).unwrap();
+    assert_eq!(gl.get_attrib_location(&program, "aPosition"), Some(0));
+    assert_eq!(gl.get_attrib_location(&program, "aUnused"), None);
+    assert_eq!(gl.get_uniform_location(&program, "uUnused"), None);
+    assert_eq!(gl.get_uniform_location(&program, "uColor"), Some(()));
+}
+
+#[test]
+fn varyings_missing_from_the_vertex_shader_fail_to_link() {
+    let gl = ValidatingBackend::default();
+    let result = gl.link_program(vertex::color_2d::SHADER, fragment::varying_color_from_vertex::SHADER, &[], &["aPosition"]);
+    assert!(matches!(result, Err(Error::Link(_))));
+}
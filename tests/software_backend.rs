@@ -0,0 +1,97 @@
+// Software backend behaviour on input a GL driver would reject or clip
+use web_sys::WebGlRenderingContext as GL;
+use rust_3d_demo::backend::buffers::VertexBuffer;
+use rust_3d_demo::backend::program::ShaderProgram;
+use rust_3d_demo::backend::software::SoftwareBackend;
+use rust_3d_demo::backend::RenderBackend;
+use rust_3d_demo::shaders::{fragment, vertex};
+
+const SIZE: usize = 8;
+
+// Draws a white triangle from clip-space positions, with an identity transform
+fn draw_triangle(gl: &SoftwareBackend, positions: &[f32]) -> ShaderProgram<SoftwareBackend> {
+    let program = ShaderProgram::new(gl, vertex::color_2d_gradient::SHADER, fragment::varying_color_from_vertex::SHADER, &[], &["aPosition", "aColor"]).unwrap();
+    program.use_program(gl);
+    let positions = VertexBuffer::with_data(gl, 4, positions, GL::STATIC_DRAW).unwrap();
+    let colors = VertexBuffer::with_data(gl, 4, &[1.; 12], GL::STATIC_DRAW).unwrap();
+    positions.bind_attribute(gl, 0, 0);
+    colors.bind_attribute(gl, 1, 0);
+    program.set_float(gl, "uOpacity", 1.);
+    program.set_mat4(gl, "uTransform", &[1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1.]);
+    gl.clear_color(0., 0., 0., 1.);
+    gl.clear(GL::COLOR_BUFFER_BIT);
+    gl.draw_arrays(GL::TRIANGLES, 0, 3);
+    program
+}
+
+fn is_lit(pixels: &[u8], x: usize, y: usize) -> bool {
+    pixels[(y * SIZE + x) * 4] > 0
+}
+
+#[test]
+fn triangle_crossing_the_near_plane_draws_its_visible_part() {
+    let gl = SoftwareBackend::new(SIZE, SIZE);
+    // The left vertex is in front of the camera, the two right ones are behind it,
+    // so the visible part is a wedge opening to the right of the left vertex
+    draw_triangle(&gl, &[-0.5, 0., 0., 1., 1., -1., 0., -1., 1., 1., 0., -1.]);
+    let pixels = gl.read_pixels();
+    assert!(is_lit(&pixels, 6, SIZE / 2));
+    assert!(is_lit(&pixels, 7, 0));
+    assert!(!is_lit(&pixels, 0, SIZE / 2));
+}
+
+#[test]
+fn triangle_behind_the_camera_is_not_drawn() {
+    let gl = SoftwareBackend::new(SIZE, SIZE);
+    draw_triangle(&gl, &[-1., -1., 0., -1., 3., -1., 0., -1., -1., 3., 0., -1.]);
+    assert!(gl.read_pixels().chunks(4).all(|pixel| pixel[..3] == [0, 0, 0]));
+}
+
+#[test]
+fn element_offset_past_the_end_draws_nothing() {
+    let gl = SoftwareBackend::new(SIZE, SIZE);
+    draw_triangle(&gl, &[-1., -1., 0., 1., 3., -1., 0., 1., -1., 3., 0., 1.]);
+    gl.clear(GL::COLOR_BUFFER_BIT);
+    let indices = gl.create_buffer().unwrap();
+    gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(&indices));
+    gl.buffer_data_u16(GL::ELEMENT_ARRAY_BUFFER, &[0, 1, 2], GL::STATIC_DRAW);
+    gl.draw_elements(GL::TRIANGLES, 3, GL::UNSIGNED_SHORT, 64);
+    gl.draw_elements(GL::TRIANGLES, 3, GL::UNSIGNED_SHORT, -2);
+    assert!(gl.read_pixels().chunks(4).all(|pixel| pixel[..3] == [0, 0, 0]));
+
+    gl.draw_elements(GL::TRIANGLES, 3, GL::UNSIGNED_SHORT, 0);
+    assert!(is_lit(&gl.read_pixels(), SIZE / 2, SIZE / 2));
+}
+
+#[test]
+fn attribute_index_past_the_limit_is_ignored() {
+    let gl = SoftwareBackend::new(SIZE, SIZE);
+    draw_triangle(&gl, &[-1., -1., 0., 1., 3., -1., 0., 1., -1., 3., 0., 1.]);
+    let expected = gl.read_pixels();
+    gl.vertex_attrib_pointer(8, 4, GL::FLOAT, false, 0, 0);
+    gl.enable_vertex_attrib_array(u32::MAX);
+    gl.draw_arrays(GL::TRIANGLES, 0, 3);
+    assert_eq!(gl.read_pixels(), expected);
+}
+
+#[test]
+fn negative_and_overflowing_draw_input_is_ignored() {
+    let gl = SoftwareBackend::new(SIZE, SIZE);
+    draw_triangle(&gl, &[-1., -1., 0., 1., 3., -1., 0., 1., -1., 3., 0., 1.]);
+    let expected = gl.read_pixels();
+    gl.clear(GL::COLOR_BUFFER_BIT);
+    gl.draw_arrays(GL::TRIANGLES, -1, 3);
+    gl.draw_arrays(GL::TRIANGLES, 0, -3);
+    gl.draw_arrays(GL::TRIANGLES, i32::MAX, 3);
+    gl.draw_elements(GL::TRIANGLES, -3, GL::UNSIGNED_SHORT, 0);
+    assert!(gl.read_pixels().chunks(4).all(|pixel| pixel[..3] == [0, 0, 0]));
+
+    // Ignored like INVALID_VALUE errors in GL, so the previous state still draws
+    gl.vertex_attrib_pointer(0, 4, GL::FLOAT, false, -16, 0);
+    gl.vertex_attrib_pointer(0, 4, GL::FLOAT, false, 0, -4);
+    gl.vertex_attrib_pointer(0, -1, GL::FLOAT, false, 0, 0);
+    let other = gl.link_program(vertex::color_2d_gradient::SHADER, fragment::varying_color_from_vertex::SHADER, &[], &[]).unwrap();
+    gl.uniform_matrix4fv(gl.get_uniform_location(&other, "uTransform").as_ref(), false, &[0.; 12]);
+    gl.draw_arrays(GL::TRIANGLES, 0, 3);
+    assert_eq!(gl.read_pixels(), expected);
+}